@@ -3,20 +3,24 @@
 //! Analyzes code complexity using tree-sitter AST parsing with .gitignore support.
 //! Produces language-agnostic metrics for scaling documentation effort.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use ignore::WalkBuilder;
 use rayon::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use fst::automaton::{Automaton, Levenshtein, Str};
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     env,
     fs,
     path::{Path, PathBuf},
     process::Command,
     time::Instant,
 };
-use tree_sitter::{Language, Node, Parser as TsParser};
+use tree_sitter::{Language, Node, Parser as TsParser, Query, QueryCursor};
 
 // Complexity thresholds for bucket classification
 const COMPLEXITY_THRESHOLD_SIMPLE: f64 = 20.0;
@@ -25,6 +29,10 @@ const COMPLEXITY_THRESHOLD_MEDIUM: f64 = 50.0;
 // Top functions limit
 const TOP_FUNCTIONS_LIMIT: usize = 10;
 
+// Default cache directory, also used to detect whether `--cache-dir` was left
+// at its clap default (and can therefore still be overridden by a config file).
+const DEFAULT_CACHE_DIR: &str = "~/.cache/github";
+
 // ============================================================================
 // Language Support
 // ============================================================================
@@ -40,6 +48,15 @@ enum SupportedLanguage {
 }
 
 impl SupportedLanguage {
+    const ALL: [SupportedLanguage; 6] = [
+        Self::Python,
+        Self::JavaScript,
+        Self::TypeScript,
+        Self::Go,
+        Self::Rust,
+        Self::Java,
+    ];
+
     fn from_extension(ext: &str) -> Option<Self> {
         match ext {
             "py" => Some(Self::Python),
@@ -74,127 +91,15 @@ impl SupportedLanguage {
         }
     }
 
-    fn function_node_types(&self) -> &'static [&'static str] {
-        match self {
-            Self::Python => &["function_definition"],
-            Self::JavaScript => &[
-                "function_declaration",
-                "method_definition",
-                "arrow_function",
-                "function_expression",
-            ],
-            Self::TypeScript => &[
-                "function_declaration",
-                "method_definition",
-                "arrow_function",
-                "function_expression",
-            ],
-            Self::Go => &["function_declaration", "method_declaration"],
-            Self::Rust => &["function_item"],
-            Self::Java => &["method_declaration", "constructor_declaration"],
-        }
-    }
-
-    fn decision_point_types(&self) -> &'static [&'static str] {
+    /// The query shipped with this tool, used unless `--queries-dir` overrides it.
+    fn default_query_source(&self) -> &'static str {
         match self {
-            Self::Python => &[
-                "if_statement",
-                "elif_clause",
-                "for_statement",
-                "while_statement",
-                "match_statement",
-                "except_clause",
-                "conditional_expression",
-            ],
-            Self::JavaScript => &[
-                "if_statement",
-                "for_statement",
-                "for_in_statement",
-                "while_statement",
-                "do_statement",
-                "switch_case",
-                "catch_clause",
-                "ternary_expression",
-            ],
-            Self::TypeScript => &[
-                "if_statement",
-                "for_statement",
-                "for_in_statement",
-                "while_statement",
-                "do_statement",
-                "switch_case",
-                "catch_clause",
-                "ternary_expression",
-            ],
-            Self::Go => &[
-                "if_statement",
-                "for_statement",
-                "expression_case",
-                "type_case",
-            ],
-            Self::Rust => &[
-                "if_expression",
-                "if_let_expression",
-                "for_expression",
-                "while_expression",
-                "loop_expression",
-                "match_arm",
-            ],
-            Self::Java => &[
-                "if_statement",
-                "for_statement",
-                "while_statement",
-                "do_statement",
-                "enhanced_for_statement",
-                "switch_label",
-                "catch_clause",
-                "ternary_expression",
-            ],
-        }
-    }
-
-    fn nesting_node_types(&self) -> &'static [&'static str] {
-        match self {
-            Self::Python => &[
-                "if_statement",
-                "elif_clause",
-                "for_statement",
-                "while_statement",
-                "match_statement",
-                "except_clause",
-            ],
-            Self::JavaScript | Self::TypeScript => &[
-                "if_statement",
-                "for_statement",
-                "for_in_statement",
-                "while_statement",
-                "do_statement",
-                "switch_case",
-                "catch_clause",
-            ],
-            Self::Go => &[
-                "if_statement",
-                "for_statement",
-                "expression_case",
-                "type_case",
-            ],
-            Self::Rust => &[
-                "if_expression",
-                "if_let_expression",
-                "for_expression",
-                "while_expression",
-                "loop_expression",
-                "match_arm",
-            ],
-            Self::Java => &[
-                "if_statement",
-                "for_statement",
-                "while_statement",
-                "do_statement",
-                "enhanced_for_statement",
-                "switch_label",
-                "catch_clause",
-            ],
+            Self::Python => include_str!("../queries/python.scm"),
+            Self::JavaScript => include_str!("../queries/javascript.scm"),
+            Self::TypeScript => include_str!("../queries/typescript.scm"),
+            Self::Go => include_str!("../queries/go.scm"),
+            Self::Rust => include_str!("../queries/rust.scm"),
+            Self::Java => include_str!("../queries/java.scm"),
         }
     }
 
@@ -210,7 +115,7 @@ impl SupportedLanguage {
 // Metrics Data Structures
 // ============================================================================
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct FunctionMetrics {
     file: String,
     name: String,
@@ -223,11 +128,14 @@ struct FunctionMetrics {
     parameter_count: usize,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct FileMetrics {
     path: String,
     language: String,
     lines_of_code: usize,
+    code_lines: usize,
+    comment_lines: usize,
+    blank_lines: usize,
     function_count: usize,
     class_count: usize,
     avg_complexity: f64,
@@ -236,11 +144,14 @@ struct FileMetrics {
     functions: Vec<FunctionMetrics>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 struct RepoSummary {
     total_files: usize,
     total_functions: usize,
     languages: HashMap<String, usize>,
+    code_lines: usize,
+    comment_lines: usize,
+    blank_lines: usize,
     complexity_score: f64,
     complexity_bucket: String,
     description: String,
@@ -254,7 +165,7 @@ struct Distribution {
     high: usize,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 struct TopFunction {
     file: String,
     name: String,
@@ -263,6 +174,16 @@ struct TopFunction {
     cognitive_complexity: usize,
 }
 
+/// A file that is both complex and frequently changed - the combination most
+/// worth refactoring, since complexity nobody touches rarely causes incidents.
+#[derive(Debug, Serialize, Clone)]
+struct Hotspot {
+    file: String,
+    complexity_score: f64,
+    churn: usize,
+    hotspot_score: f64,
+}
+
 #[derive(Debug, Serialize)]
 struct RepoMetrics {
     repository: String,
@@ -270,6 +191,7 @@ struct RepoMetrics {
     summary: RepoSummary,
     distribution: Distribution,
     top_complex_functions: Vec<TopFunction>,
+    top_hotspots: Vec<Hotspot>,
     #[serde(skip_serializing_if = "Option::is_none")]
     files: Option<Vec<FileMetrics>>,
 }
@@ -294,7 +216,7 @@ struct Args {
     repo: Option<String>,
 
     /// Directory for caching cloned repos
-    #[arg(long, default_value = "~/.cache/github")]
+    #[arg(long, default_value = DEFAULT_CACHE_DIR)]
     cache_dir: String,
 
     /// Output file (default: stdout)
@@ -304,6 +226,302 @@ struct Args {
     /// Include per-file metrics in output (verbose)
     #[arg(long)]
     include_files: bool,
+
+    /// Disable the on-disk analysis cache and re-parse every file
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Compare complexity between two git revisions of the same repo, e.g. `main..HEAD`
+    #[arg(long, value_name = "BASE..HEAD")]
+    compare: Option<String>,
+
+    /// Output format: json, sarif, or github (workflow annotations)
+    #[arg(long, default_value = "json")]
+    format: String,
+
+    /// Fail functions whose cyclomatic complexity exceeds this value
+    #[arg(long)]
+    max_cyclomatic: Option<usize>,
+
+    /// Fail functions whose cognitive complexity exceeds this value
+    #[arg(long)]
+    max_cognitive: Option<usize>,
+
+    /// Fail functions whose max nesting depth exceeds this value
+    #[arg(long)]
+    max_nesting: Option<usize>,
+
+    /// Exit with a non-zero status if any function breaches a threshold
+    #[arg(long)]
+    fail_on_complexity: bool,
+
+    /// Directory of `<language>.scm` tree-sitter query files overriding the built-in ones
+    #[arg(long)]
+    queries_dir: Option<String>,
+
+    /// Build a searchable symbol index of all function names after analysis
+    #[arg(long)]
+    index: bool,
+
+    /// Query the persisted symbol index for functions matching PATTERN (prefix or fuzzy, edit distance <= 2)
+    #[arg(long)]
+    query: Option<String>,
+
+    /// Append this run's summary to a history file and report a delta against the prior entry
+    #[arg(long)]
+    history: Option<String>,
+
+    /// Backfill `--history` by walking the last N commits of `--repo`/`--path` (requires --history)
+    #[arg(long)]
+    walk_history: Option<usize>,
+
+    /// Render a browsable static HTML report (syntax-highlighted hotspots) into this directory.
+    /// Requires building with `--features html-report`.
+    #[arg(long)]
+    html: Option<String>,
+
+    /// Write a commented default config to ./.repometrics.toml and exit
+    #[arg(long)]
+    init_config: bool,
+}
+
+// ============================================================================
+// Config File
+// ============================================================================
+//
+// Defaults are read from `./.repometrics.toml`, falling back to
+// `~/.config/repometrics/config.toml` if the local file doesn't exist. CLI
+// flags take precedence over whatever the config file sets, except that
+// `--cache-dir` and `--format` given with their default values count as unset,
+// so the config file's value wins there.
+
+#[derive(Debug, Default, Deserialize)]
+struct RepometricsConfig {
+    cache_dir: Option<String>,
+    format: Option<String>,
+    max_cyclomatic: Option<usize>,
+    max_cognitive: Option<usize>,
+    max_nesting: Option<usize>,
+    #[serde(default)]
+    ignore: Vec<String>,
+}
+
+const DEFAULT_CONFIG_TEMPLATE: &str = r#"# repometrics config file
+#
+# Checked at ./.repometrics.toml, then ~/.config/repometrics/config.toml.
+# CLI flags override these defaults, except --cache-dir and --format when
+# they are given their default values.
+
+# Directory for caching cloned repos and analysis results.
+# cache_dir = "~/.cache/github"
+
+# Output format: json, sarif, or github.
+# format = "json"
+
+# Fail functions whose complexity exceeds these values.
+# max_cyclomatic = 15
+# max_cognitive = 15
+# max_nesting = 4
+
+# Glob patterns (beyond .gitignore) to exclude from analysis, so vendored or
+# generated code doesn't inflate total_files / distort complexity_score.
+# ignore = ["**/dist/**", "**/vendor/**", "**/*.min.js"]
+"#;
+
+fn load_config() -> RepometricsConfig {
+    let local = PathBuf::from(".repometrics.toml");
+    let config_path = if local.exists() {
+        Some(local)
+    } else {
+        env::var("HOME")
+            .ok()
+            .map(|home| PathBuf::from(home).join(".config/repometrics/config.toml"))
+            .filter(|p| p.exists())
+    };
+
+    config_path
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|text| toml::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn write_default_config() -> Result<()> {
+    let path = Path::new(".repometrics.toml");
+    if path.exists() {
+        anyhow::bail!(".repometrics.toml already exists; remove it first if you want to regenerate it");
+    }
+    fs::write(path, DEFAULT_CONFIG_TEMPLATE)?;
+    eprintln!("Wrote default config to {}", path.display());
+    Ok(())
+}
+
+fn build_ignore_globset(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        match Glob::new(pattern) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(e) => eprintln!("Ignoring invalid glob '{}' in config: {}", pattern, e),
+        }
+    }
+    builder
+        .build()
+        .unwrap_or_else(|_| GlobSetBuilder::new().build().expect("empty GlobSet always builds"))
+}
+
+// ============================================================================
+// Query Registry
+// ============================================================================
+//
+// Each language's classification rules (what counts as a function, a
+// decision point, a nesting node, or a class) live in a `.scm` tree-sitter
+// query file rather than a hardcoded node-kind table. `--queries-dir` lets
+// callers override or extend a language's captures without recompiling.
+
+struct QueryRegistry {
+    queries: HashMap<SupportedLanguage, Query>,
+    // Hash of the resolved query source per language, folded into the cache
+    // key so a `--queries-dir` override can't return metrics computed from a
+    // stale set of captures.
+    source_hashes: HashMap<SupportedLanguage, String>,
+}
+
+impl QueryRegistry {
+    fn load(queries_dir: Option<&Path>) -> Result<Self> {
+        let mut queries = HashMap::new();
+        let mut source_hashes = HashMap::new();
+        for lang in SupportedLanguage::ALL {
+            let source = Self::query_source(lang, queries_dir);
+            let query = Query::new(&lang.tree_sitter_language(), &source).with_context(|| {
+                format!("invalid tree-sitter query for language '{}'", lang.name())
+            })?;
+            source_hashes.insert(lang, hash_content(source.as_bytes()));
+            queries.insert(lang, query);
+        }
+        Ok(Self { queries, source_hashes })
+    }
+
+    fn query_source(lang: SupportedLanguage, queries_dir: Option<&Path>) -> String {
+        if let Some(dir) = queries_dir {
+            let override_path = dir.join(format!("{}.scm", lang.name()));
+            if let Ok(text) = fs::read_to_string(&override_path) {
+                return text;
+            }
+        }
+        lang.default_query_source().to_string()
+    }
+
+    fn get(&self, lang: SupportedLanguage) -> &Query {
+        self.queries
+            .get(&lang)
+            .expect("QueryRegistry::load populates every SupportedLanguage")
+    }
+
+    fn query_hash(&self, lang: SupportedLanguage) -> &str {
+        self.source_hashes
+            .get(&lang)
+            .expect("QueryRegistry::load populates every SupportedLanguage")
+    }
+}
+
+// ============================================================================
+// Line Classification
+// ============================================================================
+//
+// A lightweight, tokei-style line scanner: each line is classified as code,
+// comment, or blank using a per-language table of line-comment tokens and
+// block-comment delimiter pairs. A nesting depth counter (rather than a
+// bool) is tracked because some languages (Rust) allow nested `/* */`.
+//
+// Known limitation: comment tokens appearing inside string literals (e.g. a
+// `//` inside a Python string) can be misclassified. This is a deliberate
+// heuristic trade-off, not a full lexer.
+
+struct CommentStyle {
+    line_comment: &'static [&'static str],
+    block_comment: &'static [(&'static str, &'static str)],
+}
+
+impl SupportedLanguage {
+    fn comment_style(&self) -> CommentStyle {
+        match self {
+            Self::Python => CommentStyle {
+                line_comment: &["#"],
+                block_comment: &[("\"\"\"", "\"\"\""), ("'''", "'''")],
+            },
+            Self::JavaScript | Self::TypeScript | Self::Go | Self::Java | Self::Rust => {
+                CommentStyle {
+                    line_comment: &["//"],
+                    block_comment: &[("/*", "*/")],
+                }
+            }
+        }
+    }
+}
+
+fn classify_lines(source: &str, lang: SupportedLanguage) -> (usize, usize, usize) {
+    let style = lang.comment_style();
+    let mut code_lines = 0;
+    let mut comment_lines = 0;
+    let mut blank_lines = 0;
+    let mut depth: usize = 0;
+
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+
+        let started_in_comment = depth > 0;
+        let is_line_comment =
+            depth == 0 && style.line_comment.iter().any(|tok| trimmed.starts_with(tok));
+
+        let mut saw_code = false;
+        let mut saw_comment = started_in_comment;
+        if !is_line_comment {
+            let mut rest = trimmed;
+            while !rest.is_empty() {
+                let mut advanced = false;
+                for (open, close) in style.block_comment {
+                    if depth > 0 && rest.starts_with(close) {
+                        depth -= 1;
+                        rest = &rest[close.len()..];
+                        saw_comment = true;
+                        advanced = true;
+                        break;
+                    }
+                    if rest.starts_with(open) {
+                        depth += 1;
+                        rest = &rest[open.len()..];
+                        saw_comment = true;
+                        advanced = true;
+                        break;
+                    }
+                }
+                if !advanced {
+                    if depth == 0 {
+                        saw_code = true;
+                    }
+                    let next = rest.char_indices().nth(1).map(|(i, _)| i).unwrap_or(rest.len());
+                    rest = &rest[next..];
+                }
+            }
+        }
+
+        // A line whose only content is a block comment (including one that
+        // opens and closes entirely within the line, e.g. `"""docstring"""`
+        // or `/* note */`) is a comment line even though it never "starts in"
+        // or "is" a line comment.
+        if is_line_comment || (saw_comment && !saw_code) {
+            comment_lines += 1;
+        } else {
+            code_lines += 1;
+        }
+    }
+
+    (code_lines, comment_lines, blank_lines)
 }
 
 // ============================================================================
@@ -347,18 +565,22 @@ fn clone_or_update_repo(repo_url: &str, cache_dir: &str) -> Result<PathBuf> {
 // File Discovery
 // ============================================================================
 
-fn discover_files(repo_root: &Path) -> Vec<PathBuf> {
+fn discover_files(repo_root: &Path, ignore_globs: &GlobSet) -> Vec<PathBuf> {
     let mut files = Vec::new();
+    let ignore_globs = ignore_globs.clone();
 
     let walker = WalkBuilder::new(repo_root)
         .hidden(false)
         .git_ignore(true)
         .git_global(false)
         .git_exclude(false)
-        .filter_entry(|e| {
+        .filter_entry(move |e| {
             let name = e.file_name().to_string_lossy();
             // Skip common non-source directories
-            name != ".git" && name != "node_modules" && name != "__pycache__" && name != "vendor"
+            if name == ".git" || name == "node_modules" || name == "__pycache__" || name == "vendor" {
+                return false;
+            }
+            !ignore_globs.is_match(e.path())
         })
         .build();
 
@@ -405,16 +627,52 @@ fn get_node_text<'a>(node: &Node, source: &'a [u8]) -> &'a str {
     std::str::from_utf8(&source[node.byte_range()]).unwrap_or("")
 }
 
-fn is_decision_point(node_type: &str, lang: SupportedLanguage) -> bool {
-    lang.decision_point_types().contains(&node_type)
+/// Node-id sets captured by a language's query (`@function`, `@decision`,
+/// `@nesting`, `@class`), produced once per file so the recursive complexity
+/// walks can do a plain set lookup instead of matching node-kind strings.
+#[derive(Default)]
+struct QueryNodeSets {
+    functions: HashSet<usize>,
+    decisions: HashSet<usize>,
+    nesting: HashSet<usize>,
+    classes: HashSet<usize>,
+}
+
+fn run_classification_query(query: &Query, root: tree_sitter::Node, source: &[u8]) -> QueryNodeSets {
+    let mut sets = QueryNodeSets::default();
+    let capture_names = query.capture_names();
+    let mut cursor = QueryCursor::new();
+
+    for m in cursor.matches(query, root, source) {
+        for capture in m.captures {
+            let target = match capture_names[capture.index as usize].as_str() {
+                "function" => &mut sets.functions,
+                "decision" => &mut sets.decisions,
+                "nesting" => &mut sets.nesting,
+                "class" => &mut sets.classes,
+                _ => continue,
+            };
+            target.insert(capture.node.id());
+        }
+    }
+
+    sets
+}
+
+fn is_decision_point(node: &Node, sets: &QueryNodeSets) -> bool {
+    sets.decisions.contains(&node.id())
+}
+
+fn is_nesting_node(node: &Node, sets: &QueryNodeSets) -> bool {
+    sets.nesting.contains(&node.id())
 }
 
-fn is_nesting_node(node_type: &str, lang: SupportedLanguage) -> bool {
-    lang.nesting_node_types().contains(&node_type)
+fn is_function_node(node: &Node, sets: &QueryNodeSets) -> bool {
+    sets.functions.contains(&node.id())
 }
 
-fn is_function_node(node_type: &str, lang: SupportedLanguage) -> bool {
-    lang.function_node_types().contains(&node_type)
+fn is_class_node(node: &Node, sets: &QueryNodeSets) -> bool {
+    sets.classes.contains(&node.id())
 }
 
 fn count_boolean_operators(node: Node, source: &[u8], lang: SupportedLanguage) -> usize {
@@ -455,12 +713,17 @@ fn count_boolean_operators(node: Node, source: &[u8], lang: SupportedLanguage) -
     count
 }
 
-fn calculate_cyclomatic_complexity(func_node: Node, source: &[u8], lang: SupportedLanguage) -> usize {
+fn calculate_cyclomatic_complexity(
+    func_node: Node,
+    source: &[u8],
+    lang: SupportedLanguage,
+    sets: &QueryNodeSets,
+) -> usize {
     let mut complexity = 1;
     let mut stack = vec![func_node];
 
     while let Some(node) = stack.pop() {
-        if is_decision_point(node.kind(), lang) {
+        if is_decision_point(&node, sets) {
             complexity += 1;
         }
 
@@ -487,15 +750,15 @@ fn calculate_cyclomatic_complexity(func_node: Node, source: &[u8], lang: Support
     complexity
 }
 
-fn calculate_cognitive_complexity(func_node: Node, lang: SupportedLanguage) -> usize {
-    fn visit(node: Node, nesting_level: usize, lang: SupportedLanguage) -> usize {
+fn calculate_cognitive_complexity(func_node: Node, sets: &QueryNodeSets) -> usize {
+    fn visit(node: Node, nesting_level: usize, sets: &QueryNodeSets) -> usize {
         let mut complexity = 0;
 
-        if is_decision_point(node.kind(), lang) {
+        if is_decision_point(&node, sets) {
             complexity += 1 + nesting_level;
         }
 
-        let child_nesting = if is_nesting_node(node.kind(), lang) {
+        let child_nesting = if is_nesting_node(&node, sets) {
             nesting_level + 1
         } else {
             nesting_level
@@ -503,19 +766,19 @@ fn calculate_cognitive_complexity(func_node: Node, lang: SupportedLanguage) -> u
 
         for i in 0..node.child_count() {
             if let Some(child) = node.child(i) {
-                complexity += visit(child, child_nesting, lang);
+                complexity += visit(child, child_nesting, sets);
             }
         }
 
         complexity
     }
 
-    visit(func_node, 0, lang)
+    visit(func_node, 0, sets)
 }
 
-fn calculate_max_nesting_depth(func_node: Node, lang: SupportedLanguage) -> usize {
-    fn visit(node: Node, current_depth: usize, lang: SupportedLanguage) -> usize {
-        let depth = if is_nesting_node(node.kind(), lang) {
+fn calculate_max_nesting_depth(func_node: Node, sets: &QueryNodeSets) -> usize {
+    fn visit(node: Node, current_depth: usize, sets: &QueryNodeSets) -> usize {
+        let depth = if is_nesting_node(&node, sets) {
             current_depth + 1
         } else {
             current_depth
@@ -524,14 +787,14 @@ fn calculate_max_nesting_depth(func_node: Node, lang: SupportedLanguage) -> usiz
         let mut max_depth = depth;
         for i in 0..node.child_count() {
             if let Some(child) = node.child(i) {
-                max_depth = max_depth.max(visit(child, depth, lang));
+                max_depth = max_depth.max(visit(child, depth, sets));
             }
         }
 
         max_depth
     }
 
-    visit(func_node, 0, lang)
+    visit(func_node, 0, sets)
 }
 
 fn get_function_name(func_node: Node, source: &[u8]) -> String {
@@ -588,17 +851,77 @@ fn get_parameter_count(func_node: Node) -> usize {
     count
 }
 
+// ============================================================================
+// Analysis Cache
+// ============================================================================
+//
+// FileMetrics are memoized on disk under `cache_dir`, keyed by the content
+// hash of the file, its language, and the hash of that language's query
+// source. Because the key is derived from the
+// bytes themselves, a changed file naturally produces a new key and there is
+// nothing to invalidate: stale entries just stop being looked up and can be
+// garbage-collected by age.
+
+fn hash_content(content: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content);
+    format!("{:x}", hasher.finalize())
+}
+
+fn cache_entry_path(cache_dir: &Path, lang: SupportedLanguage, query_hash: &str, hash: &str) -> PathBuf {
+    cache_dir
+        .join("analysis")
+        .join(lang.name())
+        .join(query_hash)
+        .join(format!("{}.json", hash))
+}
+
+fn read_cached_file_metrics(
+    cache_dir: &Path,
+    lang: SupportedLanguage,
+    query_hash: &str,
+    hash: &str,
+) -> Option<FileMetrics> {
+    let path = cache_entry_path(cache_dir, lang, query_hash, hash);
+    let data = fs::read(path).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+fn write_cached_file_metrics(
+    cache_dir: &Path,
+    lang: SupportedLanguage,
+    query_hash: &str,
+    hash: &str,
+    metrics: &FileMetrics,
+) {
+    let path = cache_entry_path(cache_dir, lang, query_hash, hash);
+    if let Some(parent) = path.parent() {
+        if fs::create_dir_all(parent).is_err() {
+            return;
+        }
+    }
+    if let Ok(data) = serde_json::to_vec(metrics) {
+        let _ = fs::write(path, data);
+    }
+}
+
 // ============================================================================
 // File Analysis
 // ============================================================================
 
-fn analyze_file(file_path: &Path, repo_root: &Path) -> Option<FileMetrics> {
+fn analyze_file(
+    file_path: &Path,
+    repo_root: &Path,
+    cache_dir: Option<&Path>,
+    queries: &QueryRegistry,
+) -> Option<FileMetrics> {
     let ext = file_path.extension()?.to_str()?;
     let lang = SupportedLanguage::from_extension(ext)?;
 
     let content = fs::read(file_path).ok()?;
     let source = String::from_utf8_lossy(&content);
     let lines_of_code = source.lines().count();
+    let (code_lines, comment_lines, blank_lines) = classify_lines(&source, lang);
 
     let rel_path = file_path
         .strip_prefix(repo_root)
@@ -606,6 +929,15 @@ fn analyze_file(file_path: &Path, repo_root: &Path) -> Option<FileMetrics> {
         .to_string_lossy()
         .to_string();
 
+    let content_hash = hash_content(&content);
+    let query_hash = queries.query_hash(lang);
+    if let Some(cache_dir) = cache_dir {
+        if let Some(mut cached) = read_cached_file_metrics(cache_dir, lang, query_hash, &content_hash) {
+            cached.path = rel_path;
+            return Some(cached);
+        }
+    }
+
     // Parse with tree-sitter
     let mut parser = TsParser::new();
     parser.set_language(&lang.tree_sitter_language()).ok()?;
@@ -613,20 +945,29 @@ fn analyze_file(file_path: &Path, repo_root: &Path) -> Option<FileMetrics> {
     let tree = match parser.parse(&content, None) {
         Some(t) => t,
         None => {
-            return Some(FileMetrics {
+            let metrics = FileMetrics {
                 path: rel_path,
                 language: lang.name().to_string(),
                 lines_of_code,
+                code_lines,
+                comment_lines,
+                blank_lines,
                 function_count: 0,
                 class_count: 0,
                 avg_complexity: 0.0,
                 max_complexity: 0,
                 parse_success: false,
                 functions: Vec::new(),
-            });
+            };
+            if let Some(cache_dir) = cache_dir {
+                write_cached_file_metrics(cache_dir, lang, query_hash, &content_hash, &metrics);
+            }
+            return Some(metrics);
         }
     };
 
+    let sets = run_classification_query(queries.get(lang), tree.root_node(), &content);
+
     let mut functions = Vec::new();
     let mut class_count = 0;
 
@@ -635,22 +976,21 @@ fn analyze_file(file_path: &Path, repo_root: &Path) -> Option<FileMetrics> {
         source: &[u8],
         lang: SupportedLanguage,
         rel_path: &str,
+        sets: &QueryNodeSets,
         functions: &mut Vec<FunctionMetrics>,
         class_count: &mut usize,
     ) {
-        let kind = node.kind();
-
-        if kind == "class_definition" || kind == "class_declaration" {
+        if is_class_node(&node, sets) {
             *class_count += 1;
         }
 
-        if is_function_node(kind, lang) {
+        if is_function_node(&node, sets) {
             let name = get_function_name(node, source);
             let start_line = node.start_position().row + 1;
             let end_line = node.end_position().row + 1;
-            let cyclomatic = calculate_cyclomatic_complexity(node, source, lang);
-            let cognitive = calculate_cognitive_complexity(node, lang);
-            let max_nesting = calculate_max_nesting_depth(node, lang);
+            let cyclomatic = calculate_cyclomatic_complexity(node, source, lang, sets);
+            let cognitive = calculate_cognitive_complexity(node, sets);
+            let max_nesting = calculate_max_nesting_depth(node, sets);
             let param_count = get_parameter_count(node);
 
             functions.push(FunctionMetrics {
@@ -668,7 +1008,7 @@ fn analyze_file(file_path: &Path, repo_root: &Path) -> Option<FileMetrics> {
 
         for i in 0..node.child_count() {
             if let Some(child) = node.child(i) {
-                visit_node(child, source, lang, rel_path, functions, class_count);
+                visit_node(child, source, lang, rel_path, sets, functions, class_count);
             }
         }
     }
@@ -678,6 +1018,7 @@ fn analyze_file(file_path: &Path, repo_root: &Path) -> Option<FileMetrics> {
         &content,
         lang,
         &rel_path,
+        &sets,
         &mut functions,
         &mut class_count,
     );
@@ -695,17 +1036,24 @@ fn analyze_file(file_path: &Path, repo_root: &Path) -> Option<FileMetrics> {
         .max()
         .unwrap_or(0);
 
-    Some(FileMetrics {
+    let metrics = FileMetrics {
         path: rel_path,
         language: lang.name().to_string(),
         lines_of_code,
+        code_lines,
+        comment_lines,
+        blank_lines,
         function_count: functions.len(),
         class_count,
         avg_complexity: (avg_complexity * 100.0).round() / 100.0,
         max_complexity,
         parse_success: true,
         functions,
-    })
+    };
+    if let Some(cache_dir) = cache_dir {
+        write_cached_file_metrics(cache_dir, lang, query_hash, &content_hash, &metrics);
+    }
+    Some(metrics)
 }
 
 // ============================================================================
@@ -722,11 +1070,43 @@ fn get_complexity_bucket(score: f64) -> (&'static str, &'static str) {
     }
 }
 
-fn analyze_repository(repo_path: &Path, repo_name: &str) -> Result<RepoMetrics> {
+/// Count how many commits touched each file, keyed by repo-relative path.
+/// Returns an empty map (rather than erroring) when `repo_path` isn't a git
+/// checkout, so hotspot ranking degrades to "no churn data" instead of
+/// failing the whole analysis.
+fn compute_file_churn(repo_path: &Path) -> HashMap<String, usize> {
+    let output = match Command::new("git")
+        .args(["log", "--format=%H", "--name-only"])
+        .current_dir(repo_path)
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        _ => return HashMap::new(),
+    };
+
+    let mut churn: HashMap<String, usize> = HashMap::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let line = line.trim();
+        let is_commit_sha = line.len() == 40 && line.chars().all(|c| c.is_ascii_hexdigit());
+        if line.is_empty() || is_commit_sha {
+            continue;
+        }
+        *churn.entry(line.to_string()).or_insert(0) += 1;
+    }
+    churn
+}
+
+fn analyze_repository(
+    repo_path: &Path,
+    repo_name: &str,
+    cache_dir: Option<&Path>,
+    queries: &QueryRegistry,
+    ignore_globs: &GlobSet,
+) -> Result<RepoMetrics> {
     let start = Instant::now();
 
     eprintln!("Discovering files in {}...", repo_path.display());
-    let files = discover_files(repo_path);
+    let files = discover_files(repo_path, ignore_globs);
     eprintln!("Found {} source files", files.len());
 
     // Parallel file analysis
@@ -737,7 +1117,7 @@ fn analyze_repository(repo_path: &Path, repo_name: &str) -> Result<RepoMetrics>
             if (i + 1) % 50 == 0 {
                 eprintln!("Processing file {}/{}...", i + 1, files.len());
             }
-            analyze_file(path, repo_path)
+            analyze_file(path, repo_path, cache_dir, queries)
         })
         .collect();
 
@@ -756,6 +1136,10 @@ fn analyze_repository(repo_path: &Path, repo_name: &str) -> Result<RepoMetrics>
         *languages.entry(fm.language.clone()).or_insert(0) += 1;
     }
 
+    let code_lines: usize = file_metrics.iter().map(|fm| fm.code_lines).sum();
+    let comment_lines: usize = file_metrics.iter().map(|fm| fm.comment_lines).sum();
+    let blank_lines: usize = file_metrics.iter().map(|fm| fm.blank_lines).sum();
+
     // Complexity distribution
     let low = all_functions
         .iter()
@@ -802,6 +1186,29 @@ fn analyze_repository(repo_path: &Path, repo_name: &str) -> Result<RepoMetrics>
         })
         .collect();
 
+    // Hotspots: complexity alone over-weights code nobody touches, so rank
+    // files by complexity_score * churn instead of complexity_score alone.
+    let churn = compute_file_churn(repo_path);
+    let mut hotspots: Vec<Hotspot> = file_metrics
+        .iter()
+        .map(|fm| {
+            let file_churn = *churn.get(&fm.path).unwrap_or(&0);
+            let hotspot_score = fm.avg_complexity * file_churn as f64;
+            Hotspot {
+                file: fm.path.clone(),
+                complexity_score: fm.avg_complexity,
+                churn: file_churn,
+                hotspot_score: (hotspot_score * 100.0).round() / 100.0,
+            }
+        })
+        .collect();
+    hotspots.sort_by(|a, b| {
+        b.hotspot_score
+            .partial_cmp(&a.hotspot_score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    hotspots.truncate(TOP_FUNCTIONS_LIMIT);
+
     let parse_success_count = file_metrics.iter().filter(|fm| fm.parse_success).count();
     let parse_success_rate = if total_files > 0 {
         (parse_success_count as f64 / total_files as f64 * 1000.0).round() / 10.0
@@ -818,6 +1225,9 @@ fn analyze_repository(repo_path: &Path, repo_name: &str) -> Result<RepoMetrics>
             total_files,
             total_functions,
             languages,
+            code_lines,
+            comment_lines,
+            blank_lines,
             complexity_score: (complexity_score * 100.0).round() / 100.0,
             complexity_bucket: bucket.to_string(),
             description: description.to_string(),
@@ -825,10 +1235,686 @@ fn analyze_repository(repo_path: &Path, repo_name: &str) -> Result<RepoMetrics>
         },
         distribution: Distribution { low, medium, high },
         top_complex_functions: top_complex,
+        top_hotspots: hotspots,
         files: None,
     })
 }
 
+// ============================================================================
+// Revision Comparison
+// ============================================================================
+
+#[derive(Debug, Serialize)]
+struct FunctionDelta {
+    file: String,
+    name: String,
+    cyclomatic_before: usize,
+    cyclomatic_after: usize,
+    cyclomatic_delta: i64,
+    cognitive_before: usize,
+    cognitive_after: usize,
+    cognitive_delta: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct RepoDelta {
+    base: String,
+    head: String,
+    total_functions_before: usize,
+    total_functions_after: usize,
+    languages_delta: HashMap<String, i64>,
+    regressed_functions: Vec<FunctionDelta>,
+    new_high_complexity_functions: Vec<TopFunction>,
+    removed_functions: Vec<TopFunction>,
+}
+
+fn sanitize_ref(rev: &str) -> String {
+    rev.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+fn create_worktree(repo_path: &Path, rev: &str) -> Result<PathBuf> {
+    let worktree_path = env::temp_dir().join(format!(
+        "repometrics-worktree-{}-{}",
+        sanitize_ref(rev),
+        std::process::id()
+    ));
+    if worktree_path.exists() {
+        fs::remove_dir_all(&worktree_path)?;
+    }
+
+    let status = Command::new("git")
+        .args(["worktree", "add", "--detach"])
+        .arg(&worktree_path)
+        .arg(rev)
+        .current_dir(repo_path)
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("git worktree add failed for revision '{}'", rev);
+    }
+
+    Ok(worktree_path)
+}
+
+fn remove_worktree(repo_path: &Path, worktree_path: &Path) {
+    let _ = Command::new("git")
+        .args(["worktree", "remove", "--force"])
+        .arg(worktree_path)
+        .current_dir(repo_path)
+        .status();
+}
+
+/// Discover and parse every function in `repo_path`, flattened across files.
+fn analyze_all_functions(
+    repo_path: &Path,
+    cache_dir: Option<&Path>,
+    queries: &QueryRegistry,
+    ignore_globs: &GlobSet,
+) -> (Vec<FunctionMetrics>, HashMap<String, usize>) {
+    let files = discover_files(repo_path, ignore_globs);
+    let file_metrics: Vec<FileMetrics> = files
+        .par_iter()
+        .filter_map(|path| analyze_file(path, repo_path, cache_dir, queries))
+        .collect();
+
+    let mut languages: HashMap<String, usize> = HashMap::new();
+    for fm in &file_metrics {
+        *languages.entry(fm.language.clone()).or_insert(0) += 1;
+    }
+
+    let functions = file_metrics.into_iter().flat_map(|fm| fm.functions).collect();
+    (functions, languages)
+}
+
+fn compare_revisions(
+    repo_path: &Path,
+    base: &str,
+    head: &str,
+    cache_dir: Option<&Path>,
+    queries: &QueryRegistry,
+    ignore_globs: &GlobSet,
+) -> Result<RepoDelta> {
+    let base_worktree = create_worktree(repo_path, base)?;
+    let base_result = analyze_all_functions(&base_worktree, cache_dir, queries, ignore_globs);
+    remove_worktree(repo_path, &base_worktree);
+    let (before_functions, before_languages) = base_result;
+
+    let head_worktree = create_worktree(repo_path, head)?;
+    let head_result = analyze_all_functions(&head_worktree, cache_dir, queries, ignore_globs);
+    remove_worktree(repo_path, &head_worktree);
+    let (after_functions, after_languages) = head_result;
+
+    let mut before_by_key: HashMap<(String, String), &FunctionMetrics> = HashMap::new();
+    for f in &before_functions {
+        before_by_key.insert((f.file.clone(), f.name.clone()), f);
+    }
+    let mut after_by_key: HashMap<(String, String), &FunctionMetrics> = HashMap::new();
+    for f in &after_functions {
+        after_by_key.insert((f.file.clone(), f.name.clone()), f);
+    }
+
+    let mut regressed_functions = Vec::new();
+    let mut new_high_complexity_functions = Vec::new();
+    for (key, after) in &after_by_key {
+        match before_by_key.get(key) {
+            Some(before) => {
+                let cyclomatic_delta =
+                    after.cyclomatic_complexity as i64 - before.cyclomatic_complexity as i64;
+                let cognitive_delta =
+                    after.cognitive_complexity as i64 - before.cognitive_complexity as i64;
+                if cyclomatic_delta > 0 || cognitive_delta > 0 {
+                    regressed_functions.push(FunctionDelta {
+                        file: after.file.clone(),
+                        name: after.name.clone(),
+                        cyclomatic_before: before.cyclomatic_complexity,
+                        cyclomatic_after: after.cyclomatic_complexity,
+                        cyclomatic_delta,
+                        cognitive_before: before.cognitive_complexity,
+                        cognitive_after: after.cognitive_complexity,
+                        cognitive_delta,
+                    });
+                }
+            }
+            None if after.cyclomatic_complexity > 15 => {
+                new_high_complexity_functions.push(TopFunction {
+                    file: after.file.clone(),
+                    name: after.name.clone(),
+                    line: after.line,
+                    cyclomatic_complexity: after.cyclomatic_complexity,
+                    cognitive_complexity: after.cognitive_complexity,
+                });
+            }
+            None => {}
+        }
+    }
+    regressed_functions.sort_by(|a, b| b.cyclomatic_delta.cmp(&a.cyclomatic_delta));
+    regressed_functions.truncate(TOP_FUNCTIONS_LIMIT);
+    new_high_complexity_functions.sort_by(|a, b| b.cyclomatic_complexity.cmp(&a.cyclomatic_complexity));
+
+    let removed_functions: Vec<TopFunction> = before_by_key
+        .iter()
+        .filter(|(key, _)| !after_by_key.contains_key(*key))
+        .map(|(_, f)| TopFunction {
+            file: f.file.clone(),
+            name: f.name.clone(),
+            line: f.line,
+            cyclomatic_complexity: f.cyclomatic_complexity,
+            cognitive_complexity: f.cognitive_complexity,
+        })
+        .collect();
+
+    let mut languages_delta: HashMap<String, i64> = HashMap::new();
+    for (lang, count) in &after_languages {
+        *languages_delta.entry(lang.clone()).or_insert(0) += *count as i64;
+    }
+    for (lang, count) in &before_languages {
+        *languages_delta.entry(lang.clone()).or_insert(0) -= *count as i64;
+    }
+
+    Ok(RepoDelta {
+        base: base.to_string(),
+        head: head.to_string(),
+        total_functions_before: before_functions.len(),
+        total_functions_after: after_functions.len(),
+        languages_delta,
+        regressed_functions,
+        new_high_complexity_functions,
+        removed_functions,
+    })
+}
+
+// ============================================================================
+// Time-Series History
+// ============================================================================
+//
+// `--history <file.json>` appends each run's summary to a JSON array keyed
+// by commit, turning one-shot snapshots into a trend line. `--walk-history N`
+// bootstraps that file from an existing repo by checking out its last N
+// commits in sequence.
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct HistoryEntry {
+    commit: String,
+    unix_ts: i64,
+    summary: RepoSummary,
+    top_complex_functions: Vec<TopFunction>,
+}
+
+fn current_head(repo_path: &Path) -> Option<(String, i64)> {
+    let sha_output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(repo_path)
+        .output()
+        .ok()?;
+    if !sha_output.status.success() {
+        return None;
+    }
+    let commit = String::from_utf8_lossy(&sha_output.stdout).trim().to_string();
+
+    let ts_output = Command::new("git")
+        .args(["log", "-1", "--format=%ct"])
+        .current_dir(repo_path)
+        .output()
+        .ok()?;
+    if !ts_output.status.success() {
+        return None;
+    }
+    let unix_ts = String::from_utf8_lossy(&ts_output.stdout).trim().parse().ok()?;
+
+    Some((commit, unix_ts))
+}
+
+fn load_history(path: &Path) -> Vec<HistoryEntry> {
+    fs::read(path)
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+fn save_history(path: &Path, entries: &[HistoryEntry]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    fs::write(path, serde_json::to_vec_pretty(entries)?)?;
+    Ok(())
+}
+
+/// A function's cyclomatic complexity rising by more than this is called out as a regression.
+const HISTORY_REGRESSION_THRESHOLD: i64 = 5;
+
+fn print_history_delta(previous: &HistoryEntry, current: &HistoryEntry) {
+    eprintln!(
+        "\n=== History Delta ({} -> {}) ===",
+        &previous.commit[..previous.commit.len().min(8)],
+        &current.commit[..current.commit.len().min(8)]
+    );
+    eprintln!(
+        "Complexity score: {:+.2}",
+        current.summary.complexity_score - previous.summary.complexity_score
+    );
+    eprintln!(
+        "Total functions: {:+}",
+        current.summary.total_functions as i64 - previous.summary.total_functions as i64
+    );
+
+    let previous_by_key: HashMap<(&str, &str), &TopFunction> = previous
+        .top_complex_functions
+        .iter()
+        .map(|f| ((f.file.as_str(), f.name.as_str()), f))
+        .collect();
+
+    for f in &current.top_complex_functions {
+        match previous_by_key.get(&(f.file.as_str(), f.name.as_str())) {
+            None => {
+                eprintln!(
+                    "  New in top complex functions: {}:{} {} (cyclomatic={})",
+                    f.file, f.line, f.name, f.cyclomatic_complexity
+                );
+            }
+            Some(prev) => {
+                let delta = f.cyclomatic_complexity as i64 - prev.cyclomatic_complexity as i64;
+                if delta > HISTORY_REGRESSION_THRESHOLD {
+                    eprintln!(
+                        "  Regression: {}:{} {} cyclomatic {} -> {} ({:+})",
+                        f.file, f.line, f.name, prev.cyclomatic_complexity, f.cyclomatic_complexity, delta
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn walk_history(
+    repo_path: &Path,
+    repo_name: &str,
+    commit_count: usize,
+    cache_dir: Option<&Path>,
+    queries: &QueryRegistry,
+    ignore_globs: &GlobSet,
+) -> Result<Vec<HistoryEntry>> {
+    let original_head = current_head(repo_path)
+        .map(|(sha, _)| sha)
+        .context("could not resolve current HEAD; is --path/--repo a git repository?")?;
+
+    let log_output = Command::new("git")
+        .args(["log", &format!("-{}", commit_count), "--format=%H", "--reverse"])
+        .current_dir(repo_path)
+        .output()?;
+    let shas: Vec<String> = String::from_utf8_lossy(&log_output.stdout)
+        .lines()
+        .map(|l| l.to_string())
+        .collect();
+
+    let mut entries = Vec::new();
+    for (i, sha) in shas.iter().enumerate() {
+        let status = Command::new("git")
+            .args(["checkout", "--quiet", "--detach", sha])
+            .current_dir(repo_path)
+            .status()?;
+        if !status.success() {
+            eprintln!("Skipping {}: checkout failed", sha);
+            continue;
+        }
+
+        let metrics = analyze_repository(repo_path, repo_name, cache_dir, queries, ignore_globs)?;
+        let (commit, unix_ts) = current_head(repo_path).unwrap_or_else(|| (sha.clone(), 0));
+        entries.push(HistoryEntry {
+            commit,
+            unix_ts,
+            summary: metrics.summary,
+            top_complex_functions: metrics.top_complex_functions,
+        });
+        eprintln!("Backfilled {}/{}: {}", i + 1, shas.len(), sha);
+    }
+
+    Command::new("git")
+        .args(["checkout", "--quiet", &original_head])
+        .current_dir(repo_path)
+        .status()?;
+
+    Ok(entries)
+}
+
+// ============================================================================
+// Symbol Index
+// ============================================================================
+//
+// Persists an fst-backed map of function name -> id under `cache_dir`,
+// alongside a side table of id -> matching FunctionMetrics (several
+// functions across the repo can share a name). fst requires keys inserted
+// in lexicographic order, so names are deduped and sorted via a BTreeMap
+// before streaming into the MapBuilder.
+
+fn index_paths(cache_dir: &Path) -> (PathBuf, PathBuf) {
+    let dir = cache_dir.join("index");
+    (dir.join("symbols.fst"), dir.join("symbols.json"))
+}
+
+fn build_symbol_index(functions: Vec<FunctionMetrics>, cache_dir: &Path) -> Result<()> {
+    let mut by_name: BTreeMap<String, Vec<FunctionMetrics>> = BTreeMap::new();
+    for f in functions {
+        by_name.entry(f.name.clone()).or_default().push(f);
+    }
+
+    let (fst_path, records_path) = index_paths(cache_dir);
+    if let Some(parent) = fst_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut builder = MapBuilder::new(fs::File::create(&fst_path)?)?;
+    let mut records: Vec<Vec<FunctionMetrics>> = Vec::with_capacity(by_name.len());
+    for (id, (name, funcs)) in by_name.into_iter().enumerate() {
+        builder.insert(&name, id as u64)?;
+        records.push(funcs);
+    }
+    builder.finish()?;
+
+    fs::write(&records_path, serde_json::to_vec(&records)?)?;
+    eprintln!(
+        "Indexed {} unique function names ({} total functions) into {}",
+        records.len(),
+        records.iter().map(Vec::len).sum::<usize>(),
+        fst_path.display()
+    );
+    Ok(())
+}
+
+fn query_symbol_index(cache_dir: &Path, pattern: &str) -> Result<()> {
+    let (fst_path, records_path) = index_paths(cache_dir);
+    let map = Map::new(
+        fs::read(&fst_path).context("no symbol index found; run with --index first")?,
+    )?;
+    let records: Vec<Vec<FunctionMetrics>> = serde_json::from_slice(&fs::read(&records_path)?)?;
+
+    let mut ids: BTreeSet<u64> = BTreeSet::new();
+
+    let mut stream = map.search(Str::new(pattern).starts_with()).into_stream();
+    while let Some((_, id)) = stream.next() {
+        ids.insert(id);
+    }
+
+    if let Ok(automaton) = Levenshtein::new(pattern, 2) {
+        let mut stream = map.search(automaton).into_stream();
+        while let Some((_, id)) = stream.next() {
+            ids.insert(id);
+        }
+    }
+
+    let mut matches: Vec<&FunctionMetrics> = ids
+        .iter()
+        .filter_map(|id| records.get(*id as usize))
+        .flatten()
+        .collect();
+    matches.sort_by(|a, b| b.cyclomatic_complexity.cmp(&a.cyclomatic_complexity));
+
+    for f in &matches {
+        println!(
+            "{}:{} {} {}/{}",
+            f.file, f.line, f.name, f.cyclomatic_complexity, f.cognitive_complexity
+        );
+    }
+    eprintln!("{} matching function(s)", matches.len());
+
+    Ok(())
+}
+
+// ============================================================================
+// CI Output Formats
+// ============================================================================
+
+struct Thresholds {
+    max_cyclomatic: Option<usize>,
+    max_cognitive: Option<usize>,
+    max_nesting: Option<usize>,
+}
+
+impl Thresholds {
+    fn is_active(&self) -> bool {
+        self.max_cyclomatic.is_some() || self.max_cognitive.is_some() || self.max_nesting.is_some()
+    }
+}
+
+struct ThresholdBreach<'a> {
+    function: &'a FunctionMetrics,
+    rule_id: &'static str,
+    value: usize,
+    max: usize,
+}
+
+impl<'a> ThresholdBreach<'a> {
+    /// How far over threshold determines severity: more than double the limit is an error.
+    fn level(&self) -> &'static str {
+        if self.value as f64 > self.max as f64 * 2.0 {
+            "error"
+        } else {
+            "warning"
+        }
+    }
+
+    fn overage(&self) -> usize {
+        self.value.saturating_sub(self.max)
+    }
+}
+
+fn find_breaches<'a>(functions: &'a [FunctionMetrics], thresholds: &Thresholds) -> Vec<ThresholdBreach<'a>> {
+    let mut breaches = Vec::new();
+    for f in functions {
+        if let Some(max) = thresholds.max_cyclomatic {
+            if f.cyclomatic_complexity > max {
+                breaches.push(ThresholdBreach {
+                    function: f,
+                    rule_id: "complexity/cyclomatic",
+                    value: f.cyclomatic_complexity,
+                    max,
+                });
+            }
+        }
+        if let Some(max) = thresholds.max_cognitive {
+            if f.cognitive_complexity > max {
+                breaches.push(ThresholdBreach {
+                    function: f,
+                    rule_id: "complexity/cognitive",
+                    value: f.cognitive_complexity,
+                    max,
+                });
+            }
+        }
+        if let Some(max) = thresholds.max_nesting {
+            if f.max_nesting_depth > max {
+                breaches.push(ThresholdBreach {
+                    function: f,
+                    rule_id: "complexity/nesting",
+                    value: f.max_nesting_depth,
+                    max,
+                });
+            }
+        }
+    }
+    breaches
+}
+
+fn render_sarif(breaches: &[ThresholdBreach]) -> serde_json::Value {
+    let results: Vec<serde_json::Value> = breaches
+        .iter()
+        .map(|b| {
+            serde_json::json!({
+                "ruleId": b.rule_id,
+                "level": b.level(),
+                "message": {
+                    "text": format!(
+                        "{} has {} {} ({} over threshold of {})",
+                        b.function.name, b.value, b.rule_id, b.overage(), b.max
+                    )
+                },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": b.function.file },
+                        "region": {
+                            "startLine": b.function.line,
+                            "endLine": b.function.end_line,
+                        }
+                    }
+                }]
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "$schema": "https://raw.githubusercontent.com/oasis-tcs/sarif-spec/master/Schemata/sarif-schema-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "repometrics",
+                    "informationUri": "https://github.com/boxabirds/making-ai-agents-showcase",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": [
+                        { "id": "complexity/cyclomatic" },
+                        { "id": "complexity/cognitive" },
+                        { "id": "complexity/nesting" },
+                    ]
+                }
+            },
+            "results": results
+        }]
+    })
+}
+
+fn print_github_annotations(breaches: &[ThresholdBreach]) {
+    for b in breaches {
+        println!(
+            "::{} file={},line={},endLine={}::{} breaches {} ({} > {})",
+            b.level(),
+            b.function.file,
+            b.function.line,
+            b.function.end_line,
+            b.function.name,
+            b.rule_id,
+            b.value,
+            b.max
+        );
+    }
+}
+
+// ============================================================================
+// HTML Report (feature = "html-report")
+// ============================================================================
+
+/// Turn a repo-relative path into a filesystem-safe page name.
+#[cfg(feature = "html-report")]
+fn slugify_path(path: &str) -> String {
+    path.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Render `source` as syntect-highlighted HTML for `extension`, returning the
+/// `<code>`-free markup plus the theme's CSS (shared across pages).
+#[cfg(feature = "html-report")]
+fn highlight_source(source: &str, extension: &str) -> Result<(String, String)> {
+    use syntect::easy::ClassedHTMLGenerator;
+    use syntect::html::{css_for_theme_with_class_style, ClassStyle};
+    use syntect::parsing::SyntaxSet;
+
+    let syntax_set = SyntaxSet::load_defaults_newlines();
+    let theme_set = syntect::highlighting::ThemeSet::load_defaults();
+    let theme = &theme_set.themes["base16-ocean.dark"];
+    let css = css_for_theme_with_class_style(theme, ClassStyle::Spaced)?;
+
+    let syntax = syntax_set
+        .find_syntax_by_extension(extension)
+        .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, &syntax_set, ClassStyle::Spaced);
+    for line in source.lines() {
+        generator.parse_html_for_line_which_includes_newline(&format!("{}\n", line))?;
+    }
+    Ok((generator.finalize(), css))
+}
+
+#[cfg(feature = "html-report")]
+const HTML_INDEX_TEMPLATE: &str = include_str!("../templates/index.html.tera");
+#[cfg(feature = "html-report")]
+const HTML_FILE_TEMPLATE: &str = include_str!("../templates/file.html.tera");
+
+/// Render a browsable static HTML report: an index page with the complexity
+/// distribution and a sortable file table, plus one page per analyzed file
+/// with syntax-highlighted source and inline per-function complexity.
+#[cfg(feature = "html-report")]
+fn render_html_report(
+    metrics: &RepoMetrics,
+    file_metrics: &[FileMetrics],
+    repo_root: &Path,
+    out_dir: &Path,
+) -> Result<()> {
+    use tera::{Context, Tera};
+
+    fs::create_dir_all(out_dir)?;
+    fs::create_dir_all(out_dir.join("files"))?;
+
+    let mut tera = Tera::default();
+    tera.add_raw_template("index.html", HTML_INDEX_TEMPLATE)?;
+
+    let readme_html = ["README.md", "Readme.md", "readme.md"]
+        .iter()
+        .find_map(|name| fs::read_to_string(repo_root.join(name)).ok())
+        .map(|text| {
+            let mut html = String::new();
+            pulldown_cmark::html::push_html(&mut html, pulldown_cmark::Parser::new(&text));
+            html
+        });
+
+    let total = (metrics.distribution.low + metrics.distribution.medium + metrics.distribution.high).max(1) as f64;
+
+    let mut sorted_files: Vec<&FileMetrics> = file_metrics.iter().collect();
+    sorted_files.sort_by(|a, b| b.max_complexity.cmp(&a.max_complexity));
+    let index_rows: Vec<serde_json::Value> = sorted_files
+        .iter()
+        .map(|fm| {
+            serde_json::json!({
+                "path": fm.path,
+                "slug": slugify_path(&fm.path),
+                "language": fm.language,
+                "function_count": fm.function_count,
+                "max_complexity": fm.max_complexity,
+            })
+        })
+        .collect();
+
+    let mut ctx = Context::new();
+    ctx.insert("repo_name", &metrics.repository);
+    ctx.insert("description", &metrics.summary.description);
+    ctx.insert("readme_html", &readme_html);
+    ctx.insert("low_pct", &(metrics.distribution.low as f64 / total * 100.0));
+    ctx.insert("medium_pct", &(metrics.distribution.medium as f64 / total * 100.0));
+    ctx.insert("high_pct", &(metrics.distribution.high as f64 / total * 100.0));
+    ctx.insert("files", &index_rows);
+    fs::write(out_dir.join("index.html"), tera.render("index.html", &ctx)?)?;
+
+    // Per-file pages are independent of each other, so hand them to rayon.
+    file_metrics.par_iter().try_for_each(|fm| -> Result<()> {
+        let source = fs::read_to_string(repo_root.join(&fm.path)).unwrap_or_default();
+        let extension = Path::new(&fm.path).extension().and_then(|e| e.to_str()).unwrap_or("txt");
+        let (source_html, syntax_css) = highlight_source(&source, extension)?;
+
+        let mut tera = Tera::default();
+        tera.add_raw_template("file.html", HTML_FILE_TEMPLATE)?;
+        let mut ctx = Context::new();
+        ctx.insert("path", &fm.path);
+        ctx.insert("syntax_css", &syntax_css);
+        ctx.insert("source_html", &source_html);
+        ctx.insert("functions", &fm.functions);
+        let html = tera.render("file.html", &ctx)?;
+        fs::write(out_dir.join("files").join(format!("{}.html", slugify_path(&fm.path))), html)?;
+        Ok(())
+    })?;
+
+    eprintln!("HTML report written to {}", out_dir.display());
+    Ok(())
+}
+
 // ============================================================================
 // Main
 // ============================================================================
@@ -836,13 +1922,41 @@ fn analyze_repository(repo_path: &Path, repo_name: &str) -> Result<RepoMetrics>
 fn main() -> Result<()> {
     let args = Args::parse();
 
+    if args.init_config {
+        return write_default_config();
+    }
+
+    let config = load_config();
+    let ignore_globs = build_ignore_globset(&config.ignore);
+
+    let cache_dir_str = if args.cache_dir == DEFAULT_CACHE_DIR {
+        config.cache_dir.clone().unwrap_or_else(|| args.cache_dir.clone())
+    } else {
+        args.cache_dir.clone()
+    };
+    let format = if args.format == "json" {
+        config.format.clone().unwrap_or_else(|| args.format.clone())
+    } else {
+        args.format.clone()
+    };
+    let max_cyclomatic = args.max_cyclomatic.or(config.max_cyclomatic);
+    let max_cognitive = args.max_cognitive.or(config.max_cognitive);
+    let max_nesting = args.max_nesting.or(config.max_nesting);
+
+    if let Some(pattern) = &args.query {
+        let cache_dir = PathBuf::from(
+            cache_dir_str.replace("~", &env::var("HOME").unwrap_or_default()),
+        );
+        return query_symbol_index(&cache_dir, pattern);
+    }
+
     if args.path.is_none() && args.repo.is_none() {
         anyhow::bail!("Either --path or --repo is required");
     }
 
     // Resolve repository path
     let (repo_path, repo_name) = if let Some(ref repo_url) = args.repo {
-        let path = clone_or_update_repo(repo_url, &args.cache_dir)?;
+        let path = clone_or_update_repo(repo_url, &cache_dir_str)?;
         let name = repo_url
             .trim_end_matches('/')
             .split('/')
@@ -864,17 +1978,141 @@ fn main() -> Result<()> {
         (path.canonicalize()?, name)
     };
 
+    let cache_dir = if args.no_cache {
+        None
+    } else {
+        Some(PathBuf::from(
+            cache_dir_str.replace("~", &env::var("HOME").unwrap_or_default()),
+        ))
+    };
+
+    let queries_dir = args.queries_dir.as_ref().map(PathBuf::from);
+    let queries = QueryRegistry::load(queries_dir.as_deref())?;
+
+    if let Some(compare) = &args.compare {
+        let (base, head) = compare
+            .split_once("..")
+            .ok_or_else(|| anyhow::anyhow!("--compare expects BASE..HEAD, got '{}'", compare))?;
+
+        eprintln!("Comparing {} ({}..{})...", repo_name, base, head);
+        let delta = compare_revisions(&repo_path, base, head, cache_dir.as_deref(), &queries, &ignore_globs)?;
+        let json_output = serde_json::to_string_pretty(&delta)?;
+
+        if let Some(output_path) = &args.output {
+            fs::write(output_path, &json_output)?;
+            eprintln!("Results written to {}", output_path);
+        } else {
+            println!("{}", json_output);
+        }
+
+        eprintln!("\n=== Delta Summary ===");
+        eprintln!(
+            "Functions: {} -> {}",
+            delta.total_functions_before, delta.total_functions_after
+        );
+        eprintln!("Regressed functions: {}", delta.regressed_functions.len());
+        eprintln!(
+            "New high-complexity functions: {}",
+            delta.new_high_complexity_functions.len()
+        );
+        eprintln!("Removed functions: {}", delta.removed_functions.len());
+
+        return Ok(());
+    }
+
+    if let Some(commit_count) = args.walk_history {
+        let history_path = PathBuf::from(
+            args.history
+                .as_ref()
+                .context("--walk-history requires --history <file.json>")?,
+        );
+        let entries = walk_history(&repo_path, &repo_name, commit_count, cache_dir.as_deref(), &queries, &ignore_globs)?;
+        save_history(&history_path, &entries)?;
+        eprintln!("Wrote {} history entries to {}", entries.len(), history_path.display());
+        return Ok(());
+    }
+
     eprintln!("Analyzing {}...", repo_name);
-    let mut metrics = analyze_repository(&repo_path, &repo_name)?;
+    let mut metrics = analyze_repository(&repo_path, &repo_name, cache_dir.as_deref(), &queries, &ignore_globs)?;
+
+    let thresholds = Thresholds {
+        max_cyclomatic,
+        max_cognitive,
+        max_nesting,
+    };
 
-    if args.include_files {
-        // Re-analyze to include files
-        let files = discover_files(&repo_path);
+    if args.include_files || format != "json" || thresholds.is_active() || args.index || args.html.is_some() {
+        // Re-analyze to include files (also needed for SARIF/github output, thresholds, and
+        // the symbol index, which all require the full function list rather than just the top-N)
+        let files = discover_files(&repo_path, &ignore_globs);
         let file_metrics: Vec<FileMetrics> = files
             .par_iter()
-            .filter_map(|path| analyze_file(path, &repo_path))
+            .filter_map(|path| analyze_file(path, &repo_path, cache_dir.as_deref(), &queries))
             .collect();
-        metrics.files = Some(file_metrics);
+
+        if args.index {
+            let cache_dir = cache_dir
+                .as_deref()
+                .context("--index requires the analysis cache directory (don't pass --no-cache)")?;
+            let all_functions: Vec<FunctionMetrics> = file_metrics
+                .iter()
+                .flat_map(|fm| fm.functions.clone())
+                .collect();
+            build_symbol_index(all_functions, cache_dir)?;
+        }
+
+        if thresholds.is_active() || format != "json" {
+            let all_functions: Vec<FunctionMetrics> = file_metrics
+                .iter()
+                .flat_map(|fm| fm.functions.clone())
+                .collect();
+            let breaches = find_breaches(&all_functions, &thresholds);
+
+            match format.as_str() {
+                "sarif" => {
+                    let sarif = render_sarif(&breaches);
+                    let json_output = serde_json::to_string_pretty(&sarif)?;
+                    if let Some(output_path) = &args.output {
+                        fs::write(output_path, &json_output)?;
+                        eprintln!("Results written to {}", output_path);
+                    } else {
+                        println!("{}", json_output);
+                    }
+                }
+                "github" => {
+                    print_github_annotations(&breaches);
+                }
+                _ => {}
+            }
+
+            if args.fail_on_complexity && !breaches.is_empty() {
+                eprintln!("{} threshold breach(es) found", breaches.len());
+                std::process::exit(1);
+            }
+
+            if format != "json" {
+                if args.include_files {
+                    metrics.files = Some(file_metrics);
+                }
+                return Ok(());
+            }
+        }
+
+        if let Some(html_dir) = &args.html {
+            #[cfg(feature = "html-report")]
+            {
+                render_html_report(&metrics, &file_metrics, &repo_path, Path::new(html_dir))?;
+            }
+            #[cfg(not(feature = "html-report"))]
+            {
+                let _ = html_dir;
+                anyhow::bail!("--html requires building with `--features html-report`");
+            }
+        }
+
+        if args.include_files {
+            metrics.files = Some(file_metrics);
+        }
     }
 
     // Output JSON
@@ -894,6 +2132,10 @@ fn main() -> Result<()> {
     eprintln!("Total files: {}", metrics.summary.total_files);
     eprintln!("Total functions: {}", metrics.summary.total_functions);
     eprintln!("Languages: {:?}", metrics.summary.languages);
+    eprintln!(
+        "Lines: code={}, comment={}, blank={}",
+        metrics.summary.code_lines, metrics.summary.comment_lines, metrics.summary.blank_lines
+    );
     eprintln!(
         "Complexity score: {} ({})",
         metrics.summary.complexity_score, metrics.summary.complexity_bucket
@@ -903,5 +2145,103 @@ fn main() -> Result<()> {
         metrics.distribution.low, metrics.distribution.medium, metrics.distribution.high
     );
 
+    if let Some(history_path) = &args.history {
+        let history_path = PathBuf::from(history_path);
+        let mut history = load_history(&history_path);
+        let (commit, unix_ts) = current_head(&repo_path).unwrap_or_else(|| ("unknown".to_string(), 0));
+        let entry = HistoryEntry {
+            commit,
+            unix_ts,
+            summary: metrics.summary.clone(),
+            top_complex_functions: metrics.top_complex_functions.clone(),
+        };
+
+        if let Some(previous) = history.last() {
+            print_history_delta(previous, &entry);
+        }
+
+        history.push(entry);
+        save_history(&history_path, &history)?;
+        eprintln!("Appended history entry to {}", history_path.display());
+    }
+
     Ok(())
 }
+
+// ============================================================================
+// Line classification accuracy tests
+// ============================================================================
+//
+// Mirrors tokei's `tests/accuracy.rs`: one small fixture per supported
+// language under `tests/fixtures/accuracy/`, hand-counted, asserting exact
+// code/comment/blank line counts out of `classify_lines`. This crate ships
+// only a binary (no `src/lib.rs`), so these live as unit tests rather than
+// `tests/` integration tests, which couldn't see `classify_lines` at all.
+
+#[cfg(test)]
+mod accuracy_tests {
+    use super::*;
+
+    fn assert_counts(lang: SupportedLanguage, source: &str, expected: (usize, usize, usize)) {
+        assert_eq!(
+            classify_lines(source, lang),
+            expected,
+            "{} line counts (code, comment, blank) mismatched",
+            lang.name()
+        );
+    }
+
+    #[test]
+    fn python_accuracy() {
+        assert_counts(
+            SupportedLanguage::Python,
+            include_str!("../tests/fixtures/accuracy/sample.py"),
+            (3, 6, 2),
+        );
+    }
+
+    #[test]
+    fn javascript_accuracy() {
+        assert_counts(
+            SupportedLanguage::JavaScript,
+            include_str!("../tests/fixtures/accuracy/sample.js"),
+            (3, 6, 0),
+        );
+    }
+
+    #[test]
+    fn typescript_accuracy() {
+        assert_counts(
+            SupportedLanguage::TypeScript,
+            include_str!("../tests/fixtures/accuracy/sample.ts"),
+            (3, 6, 0),
+        );
+    }
+
+    #[test]
+    fn go_accuracy() {
+        assert_counts(
+            SupportedLanguage::Go,
+            include_str!("../tests/fixtures/accuracy/sample.go"),
+            (4, 6, 1),
+        );
+    }
+
+    #[test]
+    fn rust_accuracy() {
+        assert_counts(
+            SupportedLanguage::Rust,
+            include_str!("../tests/fixtures/accuracy/sample.rs"),
+            (3, 6, 0),
+        );
+    }
+
+    #[test]
+    fn java_accuracy() {
+        assert_counts(
+            SupportedLanguage::Java,
+            include_str!("../tests/fixtures/accuracy/sample.java"),
+            (5, 6, 0),
+        );
+    }
+}
@@ -0,0 +1,9 @@
+// crate doc
+/* single line block comment */
+/*
+ * multi-line
+ * block comment
+ */
+fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
@@ -4,13 +4,16 @@ use async_openai::{
     types::{
         ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
         ChatCompletionRequestUserMessageArgs, ChatCompletionRequestAssistantMessageArgs,
-        CreateChatCompletionRequestArgs,
+        ChatCompletionRequestToolMessageArgs, ChatCompletionTool, ChatCompletionToolArgs,
+        ChatCompletionToolType, CreateChatCompletionRequestArgs, FunctionObjectArgs,
     },
     Client,
 };
 use chrono::Local;
 use clap::Parser;
+use git2::{DiffFormat, DiffOptions, Repository};
 use ignore::WalkBuilder;
+use moka::sync::Cache;
 use serde::{Deserialize, Serialize};
 use std::{
     env,
@@ -18,7 +21,10 @@ use std::{
     io::Write,
     path::{Path, PathBuf},
     process::Command,
+    sync::mpsc,
+    time::{Duration, Instant},
 };
+use threadpool::ThreadPool;
 
 const MAX_STEPS: usize = 15;
 const REACT_SYSTEM_PROMPT: &str = r#"You are a technical documentation assistant that analyses codebases and generates comprehensive documentation.
@@ -30,7 +36,11 @@ When given a directory path and a specific analysis request, you will:
 
 You have access to tools that help you explore and understand codebases:
 - find_all_matching_files: Find files matching patterns in directories
-- read_file: Read the contents of specific files
+- read_file: Read the contents of a single file
+- read_files: Read the contents of multiple files in one call (prefer this when you already know several paths you need)
+- get_file_history: List the recent commits that touched a file, for provenance (e.g. "last rewritten in commit X")
+- blame_file: Map a file's line ranges to the commits that last changed them
+- get_diff: Get the unified diff between two revisions (base, head), for changelog or migration-doc comparisons
 
 Important guidelines:
 - Always start by exploring the directory structure to understand the codebase layout
@@ -87,6 +97,41 @@ struct Args {
     /// Base URL for the API (automatically set based on model if not provided)
     #[arg(long)]
     base_url: Option<String>,
+
+    /// Agent protocol: "tools" uses native OpenAI function calling, "react" uses
+    /// the legacy Thought/Action/Observation text format for models without it
+    #[arg(long, default_value = "tools")]
+    protocol: String,
+
+    /// Commit, tag, or branch to check out before analysis, for reproducible runs
+    #[arg(long)]
+    rev: Option<String>,
+
+    /// Restrict analysis to this subdirectory of the repository/directory
+    #[arg(long)]
+    subpath: Option<String>,
+
+    /// Compare two revisions instead of documenting a snapshot, e.g.
+    /// "v1.0..v2.0"; produces release-notes / migration documentation
+    /// describing what changed and why
+    #[arg(long)]
+    compare: Option<String>,
+
+    /// Run a benchmark sweep of the prompt against several models instead of
+    /// a single analysis, following the `cargo xtask bench` convention (a
+    /// flag here rather than a true xtask crate, since this tool isn't part
+    /// of a cargo workspace)
+    #[arg(long)]
+    bench: bool,
+
+    /// Comma-separated `vendor/model` targets for --bench, e.g.
+    /// "openai/gpt-4o-mini,google/gemini-1.5-flash"
+    #[arg(long)]
+    bench_models: Option<String>,
+
+    /// Where to append benchmark JSON-lines records, one per model run
+    #[arg(long, default_value = "bench-results.jsonl")]
+    bench_output: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -94,9 +139,25 @@ struct ToolInput {
     directory: Option<String>,
     pattern: Option<String>,
     file_path: Option<String>,
+    file_paths: Option<Vec<String>>,
+    limit: Option<usize>,
+    base: Option<String>,
+    head: Option<String>,
+    path_filter: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+/// In-memory cache of `read_file_contents` results, so repeated reads of the
+/// same path within a run are free instead of hitting disk again.
+type FileCache = Cache<String, FileReadResult>;
+
+fn new_file_cache() -> FileCache {
+    Cache::builder()
+        .max_capacity(500)
+        .time_to_live(Duration::from_secs(300))
+        .build()
+}
+
+#[derive(Debug, Clone, Serialize)]
 struct FileReadResult {
     #[serde(skip_serializing_if = "Option::is_none")]
     file: Option<String>,
@@ -112,20 +173,32 @@ struct Metadata {
     github_url: String,
     repo_name: String,
     timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit_sha: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compare_base: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compare_head: Option<String>,
 }
 
 struct Logger {
     log_file: PathBuf,
 }
 
+// Disambiguates log file names for agents constructed within the same
+// wall-clock second (e.g. `--bench` sweeping several models back to back),
+// so each run gets its own log file instead of silently sharing one.
+static LOGGER_SEQUENCE: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
 impl Logger {
     fn new() -> Result<Self> {
         let log_dir = Path::new("logs");
         fs::create_dir_all(log_dir)?;
-        
+
         let timestamp = Local::now().format("%Y%m%d-%H%M%S");
-        let log_file = log_dir.join(format!("tech-writer-{}.log", timestamp));
-        
+        let seq = LOGGER_SEQUENCE.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let log_file = log_dir.join(format!("tech-writer-{}-{}.log", timestamp, seq));
+
         Ok(Self { log_file })
     }
 
@@ -181,21 +254,29 @@ fn find_all_matching_files(
         return Ok(vec![]);
     }
 
-    let mut files = Vec::new();
-    let walker = WalkBuilder::new(dir_path)
+    let entries: Vec<PathBuf> = WalkBuilder::new(dir_path)
         .hidden(false)
         .git_ignore(true)
-        .build();
+        .build()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.is_file())
+        .collect();
 
-    for entry in walker {
-        let entry = entry?;
-        let path = entry.path();
-        
-        if path.is_file() {
+    // Match entries across a worker pool; the directory walk itself stays
+    // sequential (it's already fast), but pattern matching on large trees
+    // benefits from spreading across cores.
+    let pool = ThreadPool::new(num_cpus::get());
+    let (tx, rx) = mpsc::channel();
+    let pattern = pattern.to_string();
+    for path in entries {
+        let tx = tx.clone();
+        let pattern = pattern.clone();
+        pool.execute(move || {
             let file_name = path.file_name()
                 .and_then(|n| n.to_str())
                 .unwrap_or("");
-            
+
             // Simple glob matching
             let matches = if pattern == "*" || pattern == "*.*" {
                 true
@@ -205,70 +286,272 @@ fn find_all_matching_files(
             } else {
                 file_name == pattern
             };
-            
+
             if matches {
-                files.push(path.to_string_lossy().to_string());
+                let _ = tx.send(path.to_string_lossy().to_string());
             }
-        }
+        });
     }
+    drop(tx);
+    pool.join();
+
+    // Sorted so logs and observations stay stable across runs, independent
+    // of which worker finished first.
+    let mut files: Vec<String> = rx.iter().collect();
+    files.sort();
 
     logger.info(&format!("Found {} matching files", files.len()))?;
     Ok(files)
 }
 
-fn read_file(file_path: &str, logger: &Logger) -> Result<FileReadResult> {
-    logger.info(&format!("Tool invoked: read_file(file_path='{}')", file_path))?;
+/// Core read logic shared by the single-file and batched tools. Does not log,
+/// so it's safe to call from worker threads without interleaving log writes.
+/// Reads the literal on-disk bytes (not a committed git blob), since that's
+/// what the model's observation should reflect even in a dirty working tree;
+/// `get_file_history`/`blame_file` below are where git2 supplies provenance.
+fn read_file_contents(file_path: &str, cache: &FileCache) -> FileReadResult {
+    if let Some(cached) = cache.get(file_path) {
+        return cached;
+    }
 
     let path = Path::new(file_path);
-    if !path.exists() {
-        return Ok(FileReadResult {
+    let result = if !path.exists() {
+        FileReadResult {
             file: None,
             content: None,
             error: Some(format!("File not found: {}", file_path)),
+        }
+    } else {
+        match fs::read(path) {
+            Ok(bytes) => {
+                // Check if binary
+                if bytes.contains(&0) {
+                    FileReadResult {
+                        file: None,
+                        content: None,
+                        error: Some(format!("Cannot read binary file: {}", file_path)),
+                    }
+                } else {
+                    match String::from_utf8(bytes) {
+                        Ok(content) => FileReadResult {
+                            file: Some(file_path.to_string()),
+                            content: Some(content),
+                            error: None,
+                        },
+                        Err(_) => FileReadResult {
+                            file: None,
+                            content: None,
+                            error: Some(format!("Cannot decode file as UTF-8: {}", file_path)),
+                        },
+                    }
+                }
+            }
+            Err(e) => FileReadResult {
+                file: None,
+                content: None,
+                error: Some(format!("Failed to read file: {}", e)),
+            },
+        }
+    };
+
+    cache.insert(file_path.to_string(), result.clone());
+    result
+}
+
+fn read_file(file_path: &str, logger: &Logger, cache: &FileCache) -> Result<FileReadResult> {
+    logger.info(&format!("Tool invoked: read_file(file_path='{}')", file_path))?;
+
+    let result = read_file_contents(file_path, cache);
+    match &result.error {
+        Some(err) => logger.debug(err)?,
+        None => {
+            let char_count = result.content.as_ref().map(|c| c.len()).unwrap_or(0);
+            logger.info(&format!(
+                "Successfully read file: {} ({} chars)",
+                file_path, char_count
+            ))?;
+        }
+    }
+    Ok(result)
+}
+
+/// Read many files in one observation instead of one model round-trip per
+/// file. I/O is spread across a worker pool sized to the CPU count; results
+/// are returned sorted by path so logs stay stable regardless of which
+/// worker finishes first.
+fn read_files(file_paths: &[String], logger: &Logger, cache: &FileCache) -> Result<Vec<FileReadResult>> {
+    logger.info(&format!("Tool invoked: read_files({} files)", file_paths.len()))?;
+
+    let pool = ThreadPool::new(num_cpus::get());
+    let (tx, rx) = mpsc::channel();
+    for file_path in file_paths {
+        let tx = tx.clone();
+        let file_path = file_path.clone();
+        let cache = cache.clone();
+        pool.execute(move || {
+            let result = read_file_contents(&file_path, &cache);
+            let _ = tx.send((file_path, result));
         });
     }
+    drop(tx);
+    pool.join();
 
-    match fs::read(path) {
-        Ok(bytes) => {
-            // Check if binary
-            if bytes.contains(&0) {
-                logger.debug(&format!("File detected as binary: {}", file_path))?;
-                return Ok(FileReadResult {
-                    file: None,
-                    content: None,
-                    error: Some(format!("Cannot read binary file: {}", file_path)),
-                });
+    let mut results: Vec<(String, FileReadResult)> = rx.iter().collect();
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (file_path, result) in &results {
+        match &result.error {
+            Some(err) => logger.debug(&format!("read_files: {} -> {}", file_path, err))?,
+            None => {
+                let char_count = result.content.as_ref().map(|c| c.len()).unwrap_or(0);
+                logger.debug(&format!("read_files: {} ({} chars)", file_path, char_count))?;
             }
+        }
+    }
 
-            match String::from_utf8(bytes) {
-                Ok(content) => {
-                    let char_count = content.len();
-                    logger.info(&format!(
-                        "Successfully read file: {} ({} chars)",
-                        file_path, char_count
-                    ))?;
-                    Ok(FileReadResult {
-                        file: Some(file_path.to_string()),
-                        content: Some(content),
-                        error: None,
-                    })
-                }
-                Err(_) => Ok(FileReadResult {
-                    file: None,
-                    content: None,
-                    error: Some(format!("Cannot decode file as UTF-8: {}", file_path)),
-                }),
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+/// Open the git repository containing `path`, if any. Returns `None` (rather
+/// than an error) for plain directories, since `get_file_history`/`blame_file`
+/// should degrade gracefully outside a git checkout.
+fn repo_relative_path(path: &Path) -> Option<(Repository, PathBuf)> {
+    let repo = Repository::discover(path).ok()?;
+    let workdir = repo.workdir()?.to_path_buf();
+    let rel_path = path.strip_prefix(&workdir).ok()?.to_path_buf();
+    Some((repo, rel_path))
+}
+
+/// The last `limit` commits that touched `file_path`, most recent first.
+fn get_file_history(file_path: &str, limit: usize, logger: &Logger) -> Result<Vec<serde_json::Value>> {
+    logger.info(&format!(
+        "Tool invoked: get_file_history(file_path='{}', limit={})",
+        file_path, limit
+    ))?;
+
+    let (repo, rel_path) = match repo_relative_path(Path::new(file_path)) {
+        Some(found) => found,
+        None => {
+            logger.debug(&format!("get_file_history: not in a git repository: {}", file_path))?;
+            return Ok(vec![]);
+        }
+    };
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+    revwalk.set_sorting(git2::Sort::TIME)?;
+
+    let mut entries = Vec::new();
+    for oid in revwalk {
+        if entries.len() >= limit {
+            break;
+        }
+        let oid = oid?;
+        let commit = repo.find_commit(oid)?;
+        let tree = commit.tree()?;
+        if tree.get_path(&rel_path).is_err() {
+            continue;
+        }
+
+        let touched = match commit.parent(0) {
+            Ok(parent) => {
+                let parent_tree = parent.tree()?;
+                let diff = repo.diff_tree_to_tree(Some(&parent_tree), Some(&tree), None)?;
+                diff.deltas().any(|d| {
+                    d.new_file().path() == Some(rel_path.as_path())
+                        || d.old_file().path() == Some(rel_path.as_path())
+                })
             }
+            Err(_) => true, // root commit: it introduced every file in its tree
+        };
+        if !touched {
+            continue;
         }
-        Err(e) => Ok(FileReadResult {
-            file: None,
-            content: None,
-            error: Some(format!("Failed to read file: {}", e)),
-        }),
+
+        entries.push(serde_json::json!({
+            "commit": commit.id().to_string(),
+            "author": commit.author().name().unwrap_or("unknown"),
+            "message": commit.summary().unwrap_or(""),
+            "time": commit.time().seconds(),
+        }));
+    }
+
+    logger.info(&format!("get_file_history: {} commit(s) for {}", entries.len(), file_path))?;
+    Ok(entries)
+}
+
+/// Line-range blame for `file_path`: which commit last touched each hunk.
+fn blame_file(file_path: &str, logger: &Logger) -> Result<Vec<serde_json::Value>> {
+    logger.info(&format!("Tool invoked: blame_file(file_path='{}')", file_path))?;
+
+    let (repo, rel_path) = match repo_relative_path(Path::new(file_path)) {
+        Some(found) => found,
+        None => {
+            logger.debug(&format!("blame_file: not in a git repository: {}", file_path))?;
+            return Ok(vec![]);
+        }
+    };
+
+    let blame = repo.blame_file(&rel_path, None)?;
+    let mut hunks = Vec::new();
+    for hunk in blame.iter() {
+        let commit = repo.find_commit(hunk.final_commit_id())?;
+        hunks.push(serde_json::json!({
+            "start_line": hunk.final_start_line(),
+            "lines": hunk.lines_in_hunk(),
+            "commit": hunk.final_commit_id().to_string(),
+            "author": commit.author().name().unwrap_or("unknown"),
+            "message": commit.summary().unwrap_or(""),
+        }));
     }
+
+    logger.info(&format!("blame_file: {} hunk(s) for {}", hunks.len(), file_path))?;
+    Ok(hunks)
+}
+
+/// Unified diff between two revisions of the repository containing `repo_path`
+/// (tree-to-tree, the same approach rgit uses), with extra context lines so
+/// the agent sees surrounding code rather than just the changed hunks.
+fn get_diff(repo_path: &str, base: &str, head: &str, path_filter: Option<&str>, logger: &Logger) -> Result<String> {
+    logger.info(&format!(
+        "Tool invoked: get_diff(repo_path='{}', base='{}', head='{}', path_filter={:?})",
+        repo_path, base, head, path_filter
+    ))?;
+
+    let repo = Repository::discover(repo_path)
+        .with_context(|| format!("Not a git repository: {}", repo_path))?;
+
+    let base_tree = repo.revparse_single(base)
+        .with_context(|| format!("Could not resolve revision '{}'", base))?
+        .peel_to_tree()?;
+    let head_tree = repo.revparse_single(head)
+        .with_context(|| format!("Could not resolve revision '{}'", head))?
+        .peel_to_tree()?;
+
+    let mut diff_opts = DiffOptions::new();
+    diff_opts.context_lines(10);
+    if let Some(pattern) = path_filter {
+        diff_opts.pathspec(pattern);
+    }
+
+    let diff = repo.diff_tree_to_tree(Some(&base_tree), Some(&head_tree), Some(&mut diff_opts))?;
+
+    let mut patch = Vec::new();
+    diff.print(DiffFormat::Patch, |_delta, _hunk, line| {
+        match line.origin() {
+            '+' | '-' | ' ' => patch.push(line.origin() as u8),
+            _ => {}
+        }
+        patch.extend_from_slice(line.content());
+        true
+    })?;
+
+    let text = String::from_utf8_lossy(&patch).to_string();
+    logger.info(&format!("get_diff: {} byte(s) between {} and {}", text.len(), base, head))?;
+    Ok(text)
 }
 
-fn execute_tool(tool_name: &str, action_input: &str, logger: &Logger) -> Result<String> {
+fn execute_tool(tool_name: &str, action_input: &str, logger: &Logger, cache: &FileCache) -> Result<String> {
     logger.debug(&format!(
         "Executing tool: {} with input: {}",
         tool_name, action_input
@@ -290,15 +573,150 @@ fn execute_tool(tool_name: &str, action_input: &str, logger: &Logger) -> Result<
         }
         "read_file" => {
             let file_path = input.file_path.unwrap_or_default();
-            let result = read_file(&file_path, logger)?;
+            let result = read_file(&file_path, logger, cache)?;
             Ok(serde_json::to_string(&result)?)
         }
+        "read_files" => {
+            let file_paths = input.file_paths.unwrap_or_default();
+            let results = read_files(&file_paths, logger, cache)?;
+            Ok(serde_json::to_string(&results)?)
+        }
+        "get_file_history" => {
+            let file_path = input.file_path.unwrap_or_default();
+            let limit = input.limit.unwrap_or(10);
+            let history = get_file_history(&file_path, limit, logger)?;
+            Ok(serde_json::to_string(&history)?)
+        }
+        "blame_file" => {
+            let file_path = input.file_path.unwrap_or_default();
+            let hunks = blame_file(&file_path, logger)?;
+            Ok(serde_json::to_string(&hunks)?)
+        }
+        "get_diff" => {
+            let directory = input.directory.unwrap_or_else(|| ".".to_string());
+            let base = input.base.unwrap_or_default();
+            let head = input.head.unwrap_or_default();
+            let diff = get_diff(&directory, &base, &head, input.path_filter.as_deref(), logger)?;
+            Ok(serde_json::to_string(&serde_json::json!({ "diff": diff }))?)
+        }
         _ => Ok(serde_json::to_string(&serde_json::json!({
             "error": format!("Unknown tool: {}", tool_name)
         }))?),
     }
 }
 
+/// JSON-schema tool definitions for the native function-calling protocol.
+/// Kept in sync with the tools `execute_tool` knows how to dispatch.
+fn build_tool_definitions() -> Result<Vec<ChatCompletionTool>> {
+    let find_files_fn = FunctionObjectArgs::default()
+        .name("find_all_matching_files")
+        .description("Find files matching a glob pattern (e.g. '*.rs') under a directory")
+        .parameters(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "directory": { "type": "string", "description": "Directory to search" },
+                "pattern": { "type": "string", "description": "Glob pattern, e.g. '*.rs' or '*'" }
+            },
+            "required": ["directory", "pattern"]
+        }))
+        .build()?;
+
+    let read_file_fn = FunctionObjectArgs::default()
+        .name("read_file")
+        .description("Read the contents of a single file")
+        .parameters(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "file_path": { "type": "string", "description": "Path to the file to read" }
+            },
+            "required": ["file_path"]
+        }))
+        .build()?;
+
+    let read_files_fn = FunctionObjectArgs::default()
+        .name("read_files")
+        .description("Read the contents of multiple files in one call; results come back sorted by path")
+        .parameters(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "file_paths": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Paths of the files to read"
+                }
+            },
+            "required": ["file_paths"]
+        }))
+        .build()?;
+
+    let get_file_history_fn = FunctionObjectArgs::default()
+        .name("get_file_history")
+        .description("List the most recent commits (message, author, time) that touched a file")
+        .parameters(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "file_path": { "type": "string", "description": "Path to the file" },
+                "limit": { "type": "integer", "description": "Maximum number of commits to return (default 10)" }
+            },
+            "required": ["file_path"]
+        }))
+        .build()?;
+
+    let blame_file_fn = FunctionObjectArgs::default()
+        .name("blame_file")
+        .description("Map a file's line ranges to the commit that last changed each range")
+        .parameters(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "file_path": { "type": "string", "description": "Path to the file" }
+            },
+            "required": ["file_path"]
+        }))
+        .build()?;
+
+    let get_diff_fn = FunctionObjectArgs::default()
+        .name("get_diff")
+        .description("Get the unified diff (with extra surrounding context) between two revisions of a git repository, optionally restricted to a path")
+        .parameters(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "directory": { "type": "string", "description": "Path to the git repository" },
+                "base": { "type": "string", "description": "Base revision (commit, tag, or branch)" },
+                "head": { "type": "string", "description": "Head revision (commit, tag, or branch)" },
+                "path_filter": { "type": "string", "description": "Optional pathspec to restrict the diff to matching files" }
+            },
+            "required": ["directory", "base", "head"]
+        }))
+        .build()?;
+
+    Ok(vec![
+        ChatCompletionToolArgs::default()
+            .r#type(ChatCompletionToolType::Function)
+            .function(find_files_fn)
+            .build()?,
+        ChatCompletionToolArgs::default()
+            .r#type(ChatCompletionToolType::Function)
+            .function(read_file_fn)
+            .build()?,
+        ChatCompletionToolArgs::default()
+            .r#type(ChatCompletionToolType::Function)
+            .function(read_files_fn)
+            .build()?,
+        ChatCompletionToolArgs::default()
+            .r#type(ChatCompletionToolType::Function)
+            .function(get_file_history_fn)
+            .build()?,
+        ChatCompletionToolArgs::default()
+            .r#type(ChatCompletionToolType::Function)
+            .function(blame_file_fn)
+            .build()?,
+        ChatCompletionToolArgs::default()
+            .r#type(ChatCompletionToolType::Function)
+            .function(get_diff_fn)
+            .build()?,
+    ])
+}
+
 #[derive(Debug)]
 enum ParsedResponse {
     Final(String),
@@ -357,6 +775,7 @@ struct TechWriterAgent {
     client: Client<OpenAIConfig>,
     model_id: String,
     logger: Logger,
+    file_cache: FileCache,
 }
 
 impl TechWriterAgent {
@@ -399,12 +818,105 @@ impl TechWriterAgent {
             client,
             model_id,
             logger,
+            file_cache: new_file_cache(),
         })
     }
 
-    async fn run(&self, prompt: &str, directory: &str) -> Result<String> {
+    async fn run(&self, prompt: &str, directory: &str, protocol: &str) -> Result<String> {
+        match protocol {
+            "react" => self.run_react(prompt, directory).await,
+            "tools" => self.run_tools(prompt, directory).await,
+            other => Err(anyhow::anyhow!("Unknown protocol '{}': expected 'react' or 'tools'", other)),
+        }
+    }
+
+    /// Native OpenAI tool/function calling: the model returns structured
+    /// `tool_calls` instead of emitting Action:/Action Input: text, which is
+    /// far less fragile than parsing free-form model output.
+    async fn run_tools(&self, prompt: &str, directory: &str) -> Result<String> {
+        self.logger.info(&format!("Starting tool-calling agent with model: {}", self.model_id))?;
+
+        let tools = build_tool_definitions()?;
+
+        let mut messages = vec![
+            ChatCompletionRequestMessage::System(
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content(REACT_SYSTEM_PROMPT)
+                    .build()?
+            ),
+            ChatCompletionRequestMessage::User(
+                ChatCompletionRequestUserMessageArgs::default()
+                    .content(format!("Base directory for analysis: {}\n\n{}", directory, prompt))
+                    .build()?
+            ),
+        ];
+
+        for step in 0..MAX_STEPS {
+            self.logger.info(&format!("Step {}/{}", step + 1, MAX_STEPS))?;
+
+            let request = CreateChatCompletionRequestArgs::default()
+                .model(&self.model_id)
+                .messages(messages.clone())
+                .tools(tools.clone())
+                .temperature(0.0)
+                .build()?;
+
+            let response = self.client.chat()
+                .create(request)
+                .await?;
+
+            if let Some(usage) = &response.usage {
+                self.logger.info(&format!(
+                    "Token usage step {}: prompt={} completion={} total={}",
+                    step + 1, usage.prompt_tokens, usage.completion_tokens, usage.total_tokens
+                ))?;
+            }
+
+            let message = &response.choices[0].message;
+            let tool_calls = message.tool_calls.clone().unwrap_or_default();
+
+            if tool_calls.is_empty() {
+                let content = message.content.clone().unwrap_or_default();
+                self.logger.info("Final answer received (no further tool calls)")?;
+                return Ok(content);
+            }
+
+            // The assistant's tool-call turn must precede the matching tool
+            // messages, so echo it back before appending results.
+            messages.push(ChatCompletionRequestMessage::Assistant(
+                ChatCompletionRequestAssistantMessageArgs::default()
+                    .tool_calls(tool_calls.clone())
+                    .build()?
+            ));
+
+            // Fan out every tool call from this step before the next model turn.
+            for call in &tool_calls {
+                let observation = execute_tool(&call.function.name, &call.function.arguments, &self.logger, &self.file_cache)?;
+                self.logger.debug(&format!(
+                    "Tool result for {} ({}): {} chars",
+                    call.function.name, call.id, observation.len()
+                ))?;
+
+                messages.push(ChatCompletionRequestMessage::Tool(
+                    ChatCompletionRequestToolMessageArgs::default()
+                        .tool_call_id(call.id.clone())
+                        .content(observation)
+                        .build()?
+                ));
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "Failed to complete analysis within {} steps",
+            MAX_STEPS
+        ))
+    }
+
+    /// Legacy text-based ReAct loop (Thought/Action/Action Input/Observation),
+    /// kept for models that don't support native function calling.
+    async fn run_react(&self, prompt: &str, directory: &str) -> Result<String> {
         self.logger.info(&format!("Starting ReAct agent with model: {}", self.model_id))?;
-        
+
         let mut messages = vec![
             ChatCompletionRequestMessage::System(
                 ChatCompletionRequestSystemMessageArgs::default()
@@ -431,7 +943,14 @@ impl TechWriterAgent {
             let response = self.client.chat()
                 .create(request)
                 .await?;
-            
+
+            if let Some(usage) = &response.usage {
+                self.logger.info(&format!(
+                    "Token usage step {}: prompt={} completion={} total={}",
+                    step + 1, usage.prompt_tokens, usage.completion_tokens, usage.total_tokens
+                ))?;
+            }
+
             let content = response.choices[0].message.content.clone()
                 .unwrap_or_default();
             
@@ -452,7 +971,7 @@ impl TechWriterAgent {
                 }
                 ParsedResponse::Action { action, input } => {
                     // Execute tool
-                    let observation = execute_tool(&action, &input, &self.logger)?;
+                    let observation = execute_tool(&action, &input, &self.logger, &self.file_cache)?;
                     
                     // Add observation to messages
                     messages.push(ChatCompletionRequestMessage::User(
@@ -483,10 +1002,11 @@ fn save_results(
     output_dir: &str,
     extension: &str,
     file_name: Option<String>,
+    compare: Option<(&str, &str)>,
     logger: &Logger,
 ) -> Result<PathBuf> {
     fs::create_dir_all(output_dir)?;
-    
+
     let output_path = if let Some(name) = file_name {
         PathBuf::from(output_dir).join(name)
     } else {
@@ -494,16 +1014,23 @@ fn save_results(
         let parts: Vec<&str> = model.split('/').collect();
         let vendor = parts[0];
         let model_id = sanitize_filename(parts[1]);
-        
-        PathBuf::from(output_dir).join(format!(
-            "{}-{}-{}-{}{}",
-            timestamp, repo_name, vendor, model_id, extension
-        ))
+
+        let name = match compare {
+            Some((base, head)) => format!(
+                "{}-{}-{}..{}-{}-{}{}",
+                timestamp, repo_name, sanitize_filename(base), sanitize_filename(head), vendor, model_id, extension
+            ),
+            None => format!(
+                "{}-{}-{}-{}{}",
+                timestamp, repo_name, vendor, model_id, extension
+            ),
+        };
+        PathBuf::from(output_dir).join(name)
     };
-    
+
     fs::write(&output_path, content)?;
     logger.info(&format!("Results saved to: {}", output_path.display()))?;
-    
+
     Ok(output_path)
 }
 
@@ -512,51 +1039,338 @@ fn create_metadata(
     model: &str,
     repo_url: &str,
     repo_name: &str,
+    commit_sha: Option<String>,
+    compare: Option<(&str, &str)>,
     logger: &Logger,
 ) -> Result<()> {
     let metadata_file = output_file.with_extension("metadata.json");
-    
+
     let metadata = Metadata {
         model: model.to_string(),
         github_url: repo_url.to_string(),
         repo_name: repo_name.to_string(),
         timestamp: Local::now().to_rfc3339(),
+        commit_sha,
+        compare_base: compare.map(|(base, _)| base.to_string()),
+        compare_head: compare.map(|(_, head)| head.to_string()),
     };
-    
+
     let json = serde_json::to_string_pretty(&metadata)?;
     fs::write(&metadata_file, json)?;
-    
+
     logger.info(&format!("Metadata saved to: {}", metadata_file.display()))?;
     Ok(())
 }
 
-fn clone_or_update_repo(repo_url: &str, cache_dir: &str, logger: &Logger) -> Result<PathBuf> {
+// ============================================================================
+// Benchmark harness
+// ============================================================================
+//
+// `--bench` runs the same prompt against a set of `vendor/model` targets and
+// records one JSON-lines telemetry record per run, so step-count and token
+// regressions across models (or across commits of this crate) are diffable
+// over time. It wraps `TechWriterAgent::run` unchanged: per-step timing and
+// token usage are recovered by parsing the run's own `Logger` output rather
+// than threading instrumentation through the agent loops themselves.
+
+#[derive(Debug, Serialize)]
+struct EnvInfo {
+    hostname: String,
+    os: String,
+    arch: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit_sha: Option<String>,
+}
+
+fn collect_env_info(commit_sha: Option<String>) -> EnvInfo {
+    let hostname = Command::new("hostname")
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    EnvInfo {
+        hostname,
+        os: env::consts::OS.to_string(),
+        arch: env::consts::ARCH.to_string(),
+        commit_sha,
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct BenchRecord {
+    model: String,
+    env: EnvInfo,
+    timestamp: String,
+    max_steps: usize,
+    steps_used: usize,
+    reached_final_answer: bool,
+    step_latencies_ms: Vec<u128>,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    total_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Telemetry recovered from a single run's log file: step count, per-step
+/// wall-clock latency (from the log's own timestamps), and token usage
+/// (logged alongside each step by `run_tools`/`run_react`).
+struct RunTelemetry {
+    steps_used: usize,
+    reached_final_answer: bool,
+    step_latencies_ms: Vec<u128>,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    total_tokens: u32,
+}
+
+fn parse_run_telemetry(log_file: &Path) -> RunTelemetry {
+    let content = fs::read_to_string(log_file).unwrap_or_default();
+
+    let mut steps_used = 0;
+    let mut reached_final_answer = false;
+    let mut step_times = Vec::new();
+    let mut prompt_tokens = 0u32;
+    let mut completion_tokens = 0u32;
+    let mut total_tokens = 0u32;
+    let mut last_ts = None;
+
+    for line in content.lines() {
+        let mut fields = line.splitn(3, " - ");
+        let (timestamp, _level, message) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(t), Some(l), Some(m)) => (t, l, m),
+            _ => continue,
+        };
+        if let Ok(ts) = chrono::NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S") {
+            last_ts = Some(ts);
+        }
+
+        if let Some(rest) = message.strip_prefix("Step ") {
+            if let Some((n, _)) = rest.split_once('/') {
+                if let Ok(n) = n.trim().parse::<usize>() {
+                    steps_used = n;
+                    if let Ok(ts) = chrono::NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S") {
+                        step_times.push(ts);
+                    }
+                }
+            }
+        } else if message.starts_with("Final answer received") {
+            reached_final_answer = true;
+        } else if let Some(rest) = message.strip_prefix("Token usage step ") {
+            for token in rest.split_whitespace() {
+                if let Some(v) = token.strip_prefix("prompt=") {
+                    prompt_tokens += v.parse().unwrap_or(0);
+                } else if let Some(v) = token.strip_prefix("completion=") {
+                    completion_tokens += v.parse().unwrap_or(0);
+                } else if let Some(v) = token.strip_prefix("total=") {
+                    total_tokens += v.parse().unwrap_or(0);
+                }
+            }
+        }
+    }
+
+    // The last step ends at the run's final log line rather than at another
+    // "Step" marker, so every step gets a latency.
+    if let (false, Some(end)) = (step_times.is_empty(), last_ts) {
+        step_times.push(end);
+    }
+
+    let step_latencies_ms = step_times
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).num_milliseconds().max(0) as u128)
+        .collect();
+
+    RunTelemetry {
+        steps_used,
+        reached_final_answer,
+        step_latencies_ms,
+        prompt_tokens,
+        completion_tokens,
+        total_tokens,
+    }
+}
+
+/// Run `prompt` against every model in `models`, appending one `BenchRecord`
+/// per run to `bench_output` as it completes.
+async fn run_benchmark(
+    models: &[String],
+    prompt: &str,
+    directory: &str,
+    protocol: &str,
+    base_url: Option<String>,
+    commit_sha: Option<String>,
+    bench_output: &str,
+) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(bench_output)?;
+
+    for model in models {
+        eprintln!("Benchmarking {}...", model);
+        let start = Instant::now();
+
+        let record = match TechWriterAgent::new(model, base_url.clone()) {
+            Ok(agent) => {
+                let result = agent.run(prompt, directory, protocol).await;
+                let telemetry = parse_run_telemetry(&agent.logger.log_file);
+                BenchRecord {
+                    model: model.clone(),
+                    env: collect_env_info(commit_sha.clone()),
+                    timestamp: Local::now().to_rfc3339(),
+                    max_steps: MAX_STEPS,
+                    steps_used: telemetry.steps_used,
+                    reached_final_answer: telemetry.reached_final_answer,
+                    step_latencies_ms: telemetry.step_latencies_ms,
+                    prompt_tokens: telemetry.prompt_tokens,
+                    completion_tokens: telemetry.completion_tokens,
+                    total_tokens: telemetry.total_tokens,
+                    error: result.err().map(|e| e.to_string()),
+                }
+            }
+            Err(e) => BenchRecord {
+                model: model.clone(),
+                env: collect_env_info(commit_sha.clone()),
+                timestamp: Local::now().to_rfc3339(),
+                max_steps: MAX_STEPS,
+                steps_used: 0,
+                reached_final_answer: false,
+                step_latencies_ms: vec![],
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+                error: Some(e.to_string()),
+            },
+        };
+
+        eprintln!(
+            "  {} steps, final_answer={}, {}ms wall-clock",
+            record.steps_used, record.reached_final_answer, start.elapsed().as_millis()
+        );
+
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+    }
+
+    Ok(())
+}
+
+// ============================================================================
+// VCS Backends
+// ============================================================================
+//
+// `clone_or_update_repo` used to shell out to `git` directly. Routing it
+// through a `VcsBackend` trait means a monorepo's submodules get initialised
+// automatically and, eventually, non-git sources (Mercurial, Fossil, ...) can
+// be supported by registering another backend keyed by URL scheme.
+
+trait VcsBackend {
+    fn clone_repo(&self, url: &str, dest: &Path, logger: &Logger) -> Result<()>;
+    fn update(&self, dest: &Path, logger: &Logger) -> Result<()>;
+}
+
+struct GitBackend;
+
+impl GitBackend {
+    fn update_submodules(&self, dest: &Path, logger: &Logger) -> Result<()> {
+        logger.debug("Updating git submodules (if any)")?;
+        let status = Command::new("git")
+            .args(&["submodule", "update", "--init", "--recursive"])
+            .current_dir(dest)
+            .status()?;
+        if !status.success() {
+            logger.error(&format!("git submodule update failed in {}", dest.display()))?;
+        }
+        Ok(())
+    }
+}
+
+impl VcsBackend for GitBackend {
+    fn clone_repo(&self, url: &str, dest: &Path, logger: &Logger) -> Result<()> {
+        logger.info(&format!("Cloning repository: {}", url))?;
+        let status = Command::new("git")
+            .args(&["clone", "--quiet", url, dest.to_str().unwrap()])
+            .status()?;
+        if !status.success() {
+            anyhow::bail!("git clone failed for {}", url);
+        }
+        self.update_submodules(dest, logger)
+    }
+
+    fn update(&self, dest: &Path, logger: &Logger) -> Result<()> {
+        logger.info(&format!("Updating existing repository: {}", dest.display()))?;
+        let status = Command::new("git")
+            .args(&["pull", "--quiet"])
+            .current_dir(dest)
+            .status()?;
+        if !status.success() {
+            anyhow::bail!("git pull failed in {}", dest.display());
+        }
+        self.update_submodules(dest, logger)
+    }
+}
+
+/// Pick the `VcsBackend` responsible for `url`. `GitBackend` is the only
+/// backend today; when a second one lands (e.g. Mercurial for `hg+ssh://`
+/// URLs), dispatch here on the URL scheme instead of unconditionally
+/// returning git.
+fn resolve_backend(_url: &str) -> Box<dyn VcsBackend> {
+    Box::new(GitBackend)
+}
+
+fn clone_or_update_repo(repo_url: &str, cache_dir: &str, rev: Option<&str>, logger: &Logger) -> Result<PathBuf> {
     let repo_name = repo_url.split('/').last()
         .unwrap_or("repo")
         .trim_end_matches(".git");
     let owner = repo_url.split('/').nth(3).unwrap_or("unknown");
-    
+
     let cache_dir = cache_dir.replace("~", &env::var("HOME").unwrap_or_default());
     let cache_path = PathBuf::from(cache_dir).join(owner).join(repo_name);
-    
+
     fs::create_dir_all(cache_path.parent().unwrap())?;
-    
+
+    let backend = resolve_backend(repo_url);
     if cache_path.join(".git").exists() {
-        logger.info(&format!("Updating existing repository: {}", cache_path.display()))?;
-        Command::new("git")
-            .args(&["pull", "--quiet"])
-            .current_dir(&cache_path)
-            .status()?;
+        backend.update(&cache_path, logger)?;
     } else {
-        logger.info(&format!("Cloning repository: {}", repo_url))?;
-        Command::new("git")
-            .args(&["clone", "--quiet", repo_url, cache_path.to_str().unwrap()])
-            .status()?;
+        backend.clone_repo(repo_url, &cache_path, logger)?;
     }
-    
+
+    if let Some(rev) = rev {
+        checkout_revision(&cache_path, rev, logger)?;
+    }
+
     Ok(cache_path)
 }
 
+/// Check out an exact commit/tag/branch in detached-HEAD state, so runs
+/// against `--rev` are reproducible and cacheable per-commit.
+fn checkout_revision(repo_path: &Path, rev: &str, logger: &Logger) -> Result<()> {
+    logger.info(&format!("Checking out revision: {}", rev))?;
+    let status = Command::new("git")
+        .args(&["checkout", "--quiet", "--detach", rev])
+        .current_dir(repo_path)
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("git checkout failed for revision '{}' in {}", rev, repo_path.display());
+    }
+    Ok(())
+}
+
+/// Resolve the current HEAD commit SHA, if `repo_path` is a git checkout.
+fn resolve_commit_sha(repo_path: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .args(&["rev-parse", "HEAD"])
+        .current_dir(repo_path)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
@@ -566,13 +1380,14 @@ async fn main() -> Result<()> {
         .context("Failed to read prompt file")?;
     
     // Handle repository or directory
-    let (directory, repo_url, repo_name) = if let Some(repo) = args.repo {
+    let (directory, repo_url, repo_name, commit_sha) = if let Some(repo) = args.repo {
         let logger = Logger::new()?;
-        let dir = clone_or_update_repo(&repo, &args.cache_dir, &logger)?;
+        let dir = clone_or_update_repo(&repo, &args.cache_dir, args.rev.as_deref(), &logger)?;
         let name = repo.split('/').last()
             .unwrap_or("repo")
             .trim_end_matches(".git");
-        (dir, repo.clone(), name.to_string())
+        let commit_sha = resolve_commit_sha(&dir);
+        (dir, repo.clone(), name.to_string(), commit_sha)
     } else {
         let dir = args.directory.unwrap_or_else(|| ".".to_string());
         let path = PathBuf::from(&dir);
@@ -584,13 +1399,65 @@ async fn main() -> Result<()> {
             .and_then(|n| n.to_str())
             .unwrap_or("unknown")
             .to_string();
-        (abs_path, String::new(), name)
+        let commit_sha = resolve_commit_sha(&abs_path);
+        (abs_path, String::new(), name, commit_sha)
     };
-    
+
+    // Restrict analysis to a subdirectory, e.g. one component of a monorepo
+    let directory = if let Some(subpath) = &args.subpath {
+        let scoped = directory.join(subpath);
+        if !scoped.exists() {
+            return Err(anyhow::anyhow!("Subpath not found: {}", scoped.display()));
+        }
+        scoped
+    } else {
+        directory
+    };
+
+    // Benchmark mode sweeps several models over the same prompt instead of
+    // producing documentation, so it short-circuits before the single-run path.
+    if args.bench {
+        let models: Vec<String> = args.bench_models
+            .as_deref()
+            .unwrap_or(&args.model)
+            .split(',')
+            .map(|m| m.trim().to_string())
+            .filter(|m| !m.is_empty())
+            .collect();
+
+        return run_benchmark(
+            &models,
+            &prompt,
+            directory.to_str().unwrap(),
+            &args.protocol,
+            args.base_url,
+            commit_sha,
+            &args.bench_output,
+        ).await;
+    }
+
+    // In compare mode, document what changed between two revisions instead
+    // of a static snapshot; nudge the agent towards the get_diff tool and
+    // steer the output format towards release notes / migration docs.
+    let compare = args.compare.as_ref().map(|spec| {
+        let (base, head) = spec.split_once("..").unwrap_or((spec.as_str(), "HEAD"));
+        (base.to_string(), head.to_string())
+    });
+    let prompt = match &compare {
+        Some((base, head)) => format!(
+            "Compare mode: produce release-notes / migration documentation describing \
+what changed between revision '{base}' and revision '{head}' in this repository, \
+and why. Use the get_diff tool to inspect the changes.\n\n{prompt}"
+        ),
+        None => prompt,
+    };
+
     // Run the agent
     let agent = TechWriterAgent::new(&args.model, args.base_url)?;
-    let analysis_result = agent.run(&prompt, directory.to_str().unwrap()).await?;
-    
+    let analysis_result = agent.run(&prompt, directory.to_str().unwrap(), &args.protocol).await?;
+
+    let compare_refs = compare.as_ref().map(|(base, head)| (base.as_str(), head.as_str()));
+
     // Save results
     let output_file = save_results(
         &analysis_result,
@@ -599,11 +1466,12 @@ async fn main() -> Result<()> {
         &args.output_dir,
         &args.extension,
         args.file_name,
+        compare_refs,
         &agent.logger,
     )?;
-    
+
     // Create metadata
-    create_metadata(&output_file, &args.model, &repo_url, &repo_name, &agent.logger)?;
-    
+    create_metadata(&output_file, &args.model, &repo_url, &repo_name, commit_sha, compare_refs, &agent.logger)?;
+
     Ok(())
 }